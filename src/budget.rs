@@ -0,0 +1,176 @@
+use crate::diff::{render_line, CommitPatch, FilePatch};
+
+/// Rough token estimate; code and English average about four bytes per token.
+pub fn estimate_tokens(text: &str) -> usize {
+    text.len().div_ceil(4)
+}
+
+/// Renders `patch` within roughly `budget` tokens.
+///
+/// The budget is shared fairly between files: small files are kept whole and
+/// the largest files are cut short, or left out when nothing of them fits. A
+/// per-file stat block takes up to half of the budget, so the model still
+/// sees the extent of the change.
+pub fn truncate_patch(patch: &CommitPatch, budget: usize) -> String {
+    let mut stats = String::from("The diff was truncated to fit the model's context. Files changed:\n");
+    let stats_budget = budget / 2;
+    for (i, file) in patch.files.iter().enumerate() {
+        let stat = format!("{}\n", file.stat());
+        if estimate_tokens(&stats) + estimate_tokens(&stat) > stats_budget {
+            stats.push_str(&format!("... and {} more files\n", patch.files.len() - i));
+            break;
+        }
+        stats.push_str(&stat);
+    }
+    stats.push('\n');
+    if estimate_tokens(&stats) > budget {
+        return truncate_text(&stats, budget);
+    }
+
+    let sizes: Vec<usize> = patch
+        .files
+        .iter()
+        .map(|file| estimate_tokens(&file.to_string()))
+        .collect();
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| sizes[i]);
+
+    let mut remaining = budget.saturating_sub(estimate_tokens(&stats));
+    let mut allowances = vec![0; sizes.len()];
+    for (done, &i) in order.iter().enumerate() {
+        let share = remaining / (order.len() - done);
+        allowances[i] = sizes[i].min(share);
+        remaining -= allowances[i];
+    }
+
+    let mut output = stats;
+    for (file, allowance) in patch.files.iter().zip(allowances) {
+        if allowance == 0 {
+            continue;
+        }
+        // Even a cut file keeps its header, which may not fit.
+        let rendered = truncate_file(file, allowance);
+        if estimate_tokens(&rendered) <= allowance {
+            output.push_str(&rendered);
+        }
+    }
+    output
+}
+
+/// Renders `patch` whole if it fits in `budget` tokens, or truncated with
+/// [`truncate_patch`] otherwise.
+pub fn fit_patch(patch: &CommitPatch, budget: usize) -> String {
+    let diff = patch.to_string();
+    if estimate_tokens(&diff) <= budget {
+        diff
+    } else {
+        truncate_patch(patch, budget)
+    }
+}
+
+/// Cuts `text` to roughly `budget` tokens, at a line break where possible.
+pub fn truncate_text(text: &str, budget: usize) -> String {
+    if estimate_tokens(text) <= budget {
+        return text.to_string();
+    }
+    const NOTE: &str = "\n... (truncated)\n";
+    let mut end = (budget.saturating_sub(estimate_tokens(NOTE)) * 4).min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let cut = &text[..end];
+    let cut = cut.rfind('\n').map_or(cut, |newline| &cut[..newline]);
+    format!("{cut}{NOTE}")
+}
+
+/// Splits `patch` into rendered chunks of at most roughly `budget` tokens.
+///
+/// Files are kept together where possible; files larger than the budget are
+/// split at hunk boundaries, repeating the file header in every piece.
+pub fn chunk_patch(patch: &CommitPatch, budget: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    for file in &patch.files {
+        let rendered = file.to_string();
+        if estimate_tokens(&rendered) <= budget {
+            pieces.push(rendered);
+            continue;
+        }
+
+        let mut piece = FilePatch {
+            hunks: Vec::new(),
+            ..file.clone()
+        };
+        for hunk in &file.hunks {
+            piece.hunks.push(hunk.clone());
+            if estimate_tokens(&piece.to_string()) <= budget {
+                continue;
+            }
+            piece.hunks.pop();
+            if !piece.hunks.is_empty() {
+                pieces.push(piece.to_string());
+            }
+            piece.hunks = vec![hunk.clone()];
+            if estimate_tokens(&piece.to_string()) > budget {
+                pieces.push(truncate_file(&piece, budget));
+                piece.hunks.clear();
+            }
+        }
+        if !piece.hunks.is_empty() || file.hunks.is_empty() {
+            pieces.push(piece.to_string());
+        }
+    }
+
+    let mut chunks: Vec<String> = Vec::new();
+    for piece in pieces {
+        match chunks.last_mut() {
+            Some(chunk) if estimate_tokens(chunk) + estimate_tokens(&piece) <= budget => {
+                chunk.push_str(&piece);
+            }
+            _ => chunks.push(piece),
+        }
+    }
+    chunks
+}
+
+/// Renders as many lines of `file` as fit in `budget`, noting what was cut.
+fn truncate_file(file: &FilePatch, budget: usize) -> String {
+    let rendered = file.to_string();
+    if estimate_tokens(&rendered) <= budget {
+        return rendered;
+    }
+
+    let mut output = String::new();
+    for line in &file.header {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    let total_lines: usize = file.hunks.iter().map(|hunk| hunk.lines.len()).sum();
+    let omitted_note =
+        |omitted: usize| format!("... {omitted} more lines of {} omitted\n", file.path);
+    // Leave room for the note and the newline that may precede it.
+    let budget = budget.saturating_sub(estimate_tokens(&omitted_note(total_lines)) + 1);
+    let mut kept_lines = 0;
+    'hunks: for hunk in &file.hunks {
+        let header = hunk.header();
+        if estimate_tokens(&output) + estimate_tokens(&header) > budget {
+            break;
+        }
+        output.push_str(&header);
+        for (line_type, content) in &hunk.lines {
+            let line = render_line(*line_type, content);
+            if estimate_tokens(&output) + estimate_tokens(&line) > budget {
+                break 'hunks;
+            }
+            output.push_str(&line);
+            kept_lines += 1;
+        }
+    }
+
+    if !output.ends_with('\n') {
+        output.push('\n');
+    }
+    output.push_str(&omitted_note(total_lines - kept_lines));
+    output
+}
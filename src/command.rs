@@ -1,40 +1,80 @@
+pub(crate) mod ask;
+pub(crate) mod bookmark;
+pub(crate) mod changelog;
 pub(crate) mod describe;
+pub(crate) mod explain;
+pub(crate) mod lint;
+pub(crate) mod pr;
+pub(crate) mod resolve;
+pub(crate) mod review;
+pub(crate) mod split;
+pub(crate) mod squash;
 
-pub use describe::run_describe;
+pub use ask::run_ask;
+pub use bookmark::{run_bookmark, BookmarkResult};
+pub use changelog::{run_changelog, ChangelogFormat};
+pub use describe::{run_describe, DescribeMode};
+pub use explain::run_explain;
+pub use lint::{apply_fixes, propose_fixes, run_lint, LintFix, LintedCommit};
+pub use pr::run_pr;
+pub use resolve::{apply_resolve, plan_resolve, ResolvePlan, ResolvedFile};
+pub use review::{render_annotated, run_review, to_json, to_sarif, ReviewedCommit};
+pub use split::{apply_split, plan_split, SplitChange, SplitCommit, SplitPlan};
+pub use squash::{apply_squashes, plan_squashes, Squash, SquashPlan};
 
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::{Context, Result};
-use jj_lib::repo::ReadonlyRepo;
+use anyhow::{bail, Context, Result};
+use jj_lib::commit::Commit;
+use jj_lib::config::StackedConfig;
+use jj_lib::fileset::{self, FilesetDiagnostics};
+use jj_lib::matchers::Matcher;
+use jj_lib::repo::{ReadonlyRepo, Repo};
 use jj_lib::repo::StoreFactories;
+use jj_lib::repo_path::RepoPathUiConverter;
+use jj_lib::revset::{
+    self, RevsetAliasesMap, RevsetDiagnostics, RevsetExtensions, RevsetIteratorExt,
+    RevsetParseContext, RevsetWorkspaceContext, SymbolResolverExtension,
+};
 use jj_lib::settings::UserSettings;
 use jj_lib::workspace::{default_working_copy_factories, Workspace};
 
 use crate::config::{load_stacked_config, JjaiConfig};
+use crate::lint::lint_description;
+use crate::llm::{provider_from_config, Provider};
 
 pub struct CommandContext {
     pub cfg: JjaiConfig,
     pub workspace: Workspace,
     pub repo: Arc<ReadonlyRepo>,
+    pub provider: Arc<dyn Provider>,
 }
 
 impl CommandContext {
     pub fn init() -> Result<Self> {
         let workspace_root = std::env::var("JJ_WORKSPACE_ROOT")
-            .map(Into::into)
+            .map(PathBuf::from)
             .map_err(|_| anyhow::anyhow!("JJ_WORKSPACE_ROOT is missing"))?;
 
         let stacked_config = load_stacked_config(&workspace_root)?;
 
-        let cfg = JjaiConfig::try_from(&stacked_config)?;
+        Self::load(&workspace_root, stacked_config)
+    }
+
+    /// Opens the workspace at `workspace_root` using an already assembled
+    /// config, without consulting the environment.
+    pub fn load(workspace_root: &Path, stacked_config: StackedConfig) -> Result<Self> {
+        let mut cfg = JjaiConfig::try_from(&stacked_config)?;
+        let provider = provider_from_config(&cfg)?;
 
         let settings = UserSettings::from_config(stacked_config)
             .context("failed to load jj settings")?;
 
         let workspace = Workspace::load(
             &settings,
-            Path::new("."),
+            workspace_root,
             &StoreFactories::default(),
             &default_working_copy_factories(),
         )
@@ -45,10 +85,179 @@ impl CommandContext {
             .load_at_head()
             .context("failed to load repository")?;
 
+        let project_prompt = read_project_prompt(&workspace_root.join(cfg.prompt_file()))?;
+        let examples = history_examples(&repo, &workspace, &cfg)?;
+        cfg.set_project_style(project_prompt, examples);
+
         Ok(Self {
             cfg,
             workspace,
             repo,
+            provider,
         })
     }
 }
+
+/// Most recent `trunk()` ancestors searched for example descriptions.
+const MAX_EXAMPLE_CANDIDATES: usize = 200;
+/// Longer descriptions would crowd out the diff.
+const MAX_EXAMPLE_LEN: usize = 1000;
+
+/// Reads the checked-in prompt file, if the project has one.
+fn read_project_prompt(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(path) {
+        Ok(prompt) if prompt.trim().is_empty() => Ok(None),
+        Ok(prompt) => Ok(Some(prompt)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// The most recent descriptions on `trunk()` that pass the configured
+/// standard's lint rules, newest first.
+fn history_examples(
+    repo: &Arc<ReadonlyRepo>,
+    workspace: &Workspace,
+    cfg: &JjaiConfig,
+) -> Result<Vec<String>> {
+    if cfg.history_examples() == 0 {
+        return Ok(Vec::new());
+    }
+    // The root commit is never a merge, so the revset is never empty.
+    let commits = resolve_revisions(
+        repo,
+        workspace,
+        &format!("latest(::trunk() ~ merges(), {MAX_EXAMPLE_CANDIDATES})"),
+    )?;
+    let mut examples: Vec<String> = Vec::new();
+    for commit in &commits {
+        let description = commit.description().trim();
+        if description.is_empty()
+            || description.len() > MAX_EXAMPLE_LEN
+            || examples.iter().any(|example| example == description)
+            || !lint_description(cfg.standard(), description).is_empty()
+        {
+            continue;
+        }
+        examples.push(description.to_string());
+        if examples.len() == cfg.history_examples() {
+            break;
+        }
+    }
+    Ok(examples)
+}
+
+/// jj's built-in `trunk()` alias, which lives in the CLI's default config
+/// rather than in jj-lib.
+const DEFAULT_TRUNK_ALIAS: &str = r#"latest(
+  remote_bookmarks(exact:"main", exact:"origin") |
+  remote_bookmarks(exact:"master", exact:"origin") |
+  remote_bookmarks(exact:"trunk", exact:"origin") |
+  remote_bookmarks(exact:"main", exact:"upstream") |
+  remote_bookmarks(exact:"master", exact:"upstream") |
+  remote_bookmarks(exact:"trunk", exact:"upstream") |
+  root()
+)"#;
+
+/// Collects `revset-aliases` from the jj config, on top of the default
+/// `trunk()` alias.
+fn revset_aliases(settings: &UserSettings) -> Result<RevsetAliasesMap> {
+    let mut aliases_map = RevsetAliasesMap::new();
+    aliases_map
+        .insert("trunk()", DEFAULT_TRUNK_ALIAS)
+        .map_err(|err| anyhow::anyhow!("invalid default trunk() alias: {err}"))?;
+    for decl in settings.table_keys("revset-aliases") {
+        let defn = settings
+            .get_string(["revset-aliases", decl])
+            .with_context(|| format!("invalid revset alias '{decl}'"))?;
+        aliases_map
+            .insert(decl, defn)
+            .map_err(|err| anyhow::anyhow!("invalid revset alias '{decl}': {err}"))?;
+    }
+    Ok(aliases_map)
+}
+
+/// Interprets paths relative to the current directory within `workspace`.
+fn path_converter(workspace: &Workspace) -> RepoPathUiConverter {
+    RepoPathUiConverter::Fs {
+        cwd: std::env::current_dir().unwrap(),
+        base: workspace.workspace_root().to_owned(),
+    }
+}
+
+/// Parses a fileset expression, where bare strings are paths like in jj's
+/// own commands, into a matcher.
+pub(crate) fn parse_fileset(workspace: &Workspace, text: &str) -> Result<Box<dyn Matcher>> {
+    let mut diagnostics = FilesetDiagnostics::new();
+    let expression = fileset::parse_maybe_bare(&mut diagnostics, text, &path_converter(workspace))
+        .with_context(|| format!("failed to parse fileset '{text}'"))?;
+    Ok(expression.to_matcher())
+}
+
+/// Evaluates `revision`, which must resolve to exactly one commit.
+pub(crate) fn resolve_single_revision(
+    repo: &Arc<ReadonlyRepo>,
+    workspace: &Workspace,
+    revision: &str,
+) -> Result<Commit> {
+    let mut commits = resolve_revisions(repo, workspace, revision)?;
+    if commits.len() != 1 {
+        bail!(
+            "revision '{revision}' resolved to {} commits, expected one",
+            commits.len()
+        );
+    }
+    Ok(commits.remove(0))
+}
+
+/// Evaluates `revision` to its commits, children before parents.
+pub(crate) fn resolve_revisions(
+    repo: &Arc<ReadonlyRepo>,
+    workspace: &Workspace,
+    revision: &str,
+) -> Result<Vec<Commit>> {
+    let aliases_map = revset_aliases(repo.settings())?;
+    let extensions = RevsetExtensions::new();
+    let path_converter = path_converter(workspace);
+    let workspace_ctx = RevsetWorkspaceContext {
+        path_converter: &path_converter,
+        workspace_name: workspace.workspace_name(),
+    };
+    let context = RevsetParseContext {
+        aliases_map: &aliases_map,
+        local_variables: HashMap::new(),
+        user_email: repo.settings().user_email(),
+        date_pattern_context: chrono::Utc::now().fixed_offset().into(),
+        default_ignored_remote: None,
+        use_glob_by_default: false,
+        extensions: &extensions,
+        workspace: Some(workspace_ctx),
+    };
+
+    let mut diagnostics = RevsetDiagnostics::new();
+    let expression = revset::parse(&mut diagnostics, revision, &context)
+        .with_context(|| format!("failed to parse revision '{revision}'"))?;
+
+    let symbol_extensions: &[Arc<dyn SymbolResolverExtension>] = &[];
+    let symbol_resolver = revset::SymbolResolver::new(repo.as_ref(), symbol_extensions);
+
+    let resolved = expression
+        .resolve_user_expression(repo.as_ref(), &symbol_resolver)
+        .with_context(|| format!("failed to resolve revision '{revision}'"))?;
+
+    let revset = resolved
+        .evaluate(repo.as_ref())
+        .with_context(|| format!("failed to evaluate revision '{revision}'"))?;
+
+    let commits: Vec<_> = revset
+        .iter()
+        .commits(repo.store())
+        .collect::<Result<_, _>>()
+        .context("failed to get commits")?;
+
+    if commits.is_empty() {
+        bail!("revision '{revision}' not found");
+    }
+
+    Ok(commits)
+}
@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use jj_lib::commit::Commit;
+use jj_lib::diff_presentation::unified::DiffLineType;
+
+use super::{resolve_revisions, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::diff::{commit_patch, CommitPatch};
+use crate::llm::answer_question;
+
+/// Most recent commits searched, so asking over all of history stays fast.
+const MAX_SEARCHED: usize = 500;
+/// Most commits packed into the prompt.
+const MAX_PACKED: usize = 10;
+/// Matches in the description count this many times more than in the diff.
+const DESCRIPTION_WEIGHT: f64 = 3.0;
+
+const STOP_WORDS: &[&str] = &[
+    "and", "are", "did", "does", "for", "from", "had", "has", "have", "how", "into", "its",
+    "the", "that", "this", "was", "were", "what", "when", "where", "which", "who", "why",
+    "with", "you",
+];
+
+struct Candidate<'a> {
+    commit: &'a Commit,
+    patch: CommitPatch,
+    score: f64,
+}
+
+/// Answers `question` from the commits in `revision` that match it best,
+/// streaming the answer to `on_text`.
+///
+/// Commits are ranked by how often the question's words appear in their
+/// descriptions and changed lines, with words that appear in many commits
+/// counting less.
+pub async fn run_ask(
+    ctx: CommandContext,
+    question: &str,
+    revision: &str,
+    on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+) -> Result<()> {
+    let terms = search_terms(question);
+    if terms.is_empty() {
+        bail!("the question has no words to search for");
+    }
+
+    let commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+    let mut candidates = Vec::new();
+    let mut counts = Vec::new();
+    for commit in commits.iter().take(MAX_SEARCHED) {
+        let patch = commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
+        let description = commit.description().to_lowercase();
+        let changed = changed_text(&patch).to_lowercase();
+        counts.push(
+            terms
+                .iter()
+                .map(|term| (description.matches(term).count(), changed.matches(term).count()))
+                .collect::<Vec<_>>(),
+        );
+        candidates.push(Candidate {
+            commit,
+            patch,
+            score: 0.0,
+        });
+    }
+
+    // Inverse document frequency, so rare words decide the ranking.
+    let searched = candidates.len() as f64;
+    let weights: Vec<f64> = (0..terms.len())
+        .map(|i| {
+            let matching = counts
+                .iter()
+                .filter(|count| count[i] != (0, 0))
+                .count() as f64;
+            (searched / (1.0 + matching)).ln().max(0.0) + 1.0
+        })
+        .collect();
+    for (candidate, count) in candidates.iter_mut().zip(&counts) {
+        candidate.score = count
+            .iter()
+            .zip(&weights)
+            .map(|(&(in_description, in_diff), weight)| {
+                let hits = DESCRIPTION_WEIGHT * (in_description.min(3) as f64)
+                    + (in_diff.min(5) as f64);
+                hits * weight
+            })
+            .sum();
+    }
+
+    candidates.retain(|candidate| candidate.score > 0.0);
+    if candidates.is_empty() {
+        bail!("no commits in '{revision}' mention the question's words");
+    }
+    // Stable sort keeps newer commits first among equal scores.
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(MAX_PACKED);
+
+    let mut budget = ctx
+        .cfg
+        .token_budget()
+        .saturating_sub(estimate_tokens(question));
+    let share = budget / candidates.len();
+    let mut context = String::new();
+    for candidate in &candidates {
+        let commit = candidate.commit;
+        let date = chrono::DateTime::from_timestamp_millis(commit.author().timestamp.timestamp.0)
+            .map(|date| date.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let mut entry = format!(
+            "Commit {} ({date})\n",
+            &commit.change_id().reverse_hex()[..8]
+        );
+        match commit.description().trim() {
+            "" => entry.push_str("(no description)\n"),
+            description => {
+                entry.push_str(description);
+                entry.push('\n');
+            }
+        }
+        let header_tokens = estimate_tokens(&entry);
+        if header_tokens > budget {
+            break;
+        }
+        // Each diff gets an equal share of the budget, or what is left of it.
+        let diff_budget = share.min(budget - header_tokens);
+        entry.push_str(&fit_patch(&candidate.patch, diff_budget));
+        entry.push('\n');
+        budget = budget.saturating_sub(estimate_tokens(&entry));
+        context.push_str(&entry);
+    }
+
+    answer_question(ctx.provider.as_ref(), &ctx.cfg, question, &context, on_text).await
+}
+
+/// The distinct lowercase words of `question` worth searching for.
+fn search_terms(question: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    question
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .map(str::to_lowercase)
+        .filter(|word| word.chars().count() >= 3 && !STOP_WORDS.contains(&word.as_str()))
+        .filter(|word| seen.insert(word.clone()))
+        .collect()
+}
+
+/// The added and removed lines of `patch` and the paths it touches.
+fn changed_text(patch: &CommitPatch) -> String {
+    let mut text = String::new();
+    for file in &patch.files {
+        text.push_str(&file.path);
+        text.push('\n');
+        for hunk in &file.hunks {
+            for (line_type, content) in &hunk.lines {
+                if *line_type != DiffLineType::Context {
+                    text.push_str(content);
+                }
+            }
+        }
+    }
+    text
+}
@@ -0,0 +1,137 @@
+use anyhow::{bail, Context, Result};
+use jj_lib::object_id::ObjectId;
+use jj_lib::op_store::RefTarget;
+use jj_lib::ref_name::RefName;
+
+use super::{resolve_single_revision, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::diff::commit_patch;
+use crate::llm::suggest_bookmark;
+
+/// Longest slug kept from the model's suggestion, in bytes.
+const MAX_SLUG_LEN: usize = 40;
+
+pub struct BookmarkResult {
+    pub name: String,
+    pub change_id: String,
+    pub created: bool,
+}
+
+/// Generates a bookmark name for the commit at `revision` from the
+/// configured pattern and, unless `dry_run`, creates it there.
+///
+/// Names already taken get a numeric suffix.
+pub async fn run_bookmark(ctx: CommandContext, revision: &str, dry_run: bool) -> Result<BookmarkResult> {
+    let commit = resolve_single_revision(&ctx.repo, &ctx.workspace, revision)?;
+
+    let patch = commit_patch(ctx.repo.as_ref(), &commit, ctx.cfg.ignore()).await?;
+    let description = commit.description().trim();
+    if description.is_empty() && patch.is_empty() {
+        bail!("commit has no description or changes to name a bookmark after");
+    }
+
+    let mut prompt = String::new();
+    if !description.is_empty() {
+        prompt.push_str(&format!("Description:\n{description}\n\n"));
+    }
+    let budget = ctx.cfg.token_budget().saturating_sub(estimate_tokens(&prompt));
+    prompt.push_str(&fit_patch(&patch, budget));
+
+    let suggestion = suggest_bookmark(ctx.provider.as_ref(), &ctx.cfg, &prompt).await?;
+
+    let settings = ctx.repo.settings();
+    let user = settings
+        .user_email()
+        .split('@')
+        .next()
+        .filter(|user| !user.is_empty())
+        .unwrap_or(settings.user_name());
+    let change_id = commit.change_id().reverse_hex();
+
+    // A title without any usable characters falls back to the change id.
+    let mut slug = slugify(&suggestion.slug);
+    if slug.is_empty() {
+        slug = change_id[..8].to_string();
+    }
+    let base = ctx
+        .cfg
+        .bookmark_pattern()
+        .replace("{user}", &slugify(user))
+        .replace("{type}", &slugify(&suggestion.kind))
+        .replace("{slug}", &slug)
+        .replace("{change}", &change_id[..8]);
+    if !is_valid_name(&base) {
+        bail!(
+            "bookmark pattern '{}' produced an invalid name '{base}'",
+            ctx.cfg.bookmark_pattern()
+        );
+    }
+
+    let view = ctx.repo.view();
+    // A local name matching a remote bookmark would start tracking it.
+    let taken = |name: &str| {
+        view.get_local_bookmark(RefName::new(name)).is_present()
+            || view
+                .all_remote_bookmarks()
+                .any(|(symbol, remote_ref)| symbol.name.as_str() == name && remote_ref.is_present())
+    };
+    let mut name = base.clone();
+    let mut suffix = 2;
+    while taken(&name) {
+        name = format!("{base}-{suffix}");
+        suffix += 1;
+    }
+
+    if dry_run {
+        return Ok(BookmarkResult {
+            name,
+            change_id,
+            created: false,
+        });
+    }
+
+    let mut tx = ctx.repo.start_transaction();
+    tx.repo_mut()
+        .set_local_bookmark_target(RefName::new(&name), RefTarget::normal(commit.id().clone()));
+    tx.commit(format!("ai bookmark {name} at {}", commit.id().hex()))
+        .context("failed to commit transaction")?;
+
+    Ok(BookmarkResult {
+        name,
+        change_id,
+        created: true,
+    })
+}
+
+/// Lowercases `text` and joins its alphanumeric words with hyphens.
+/// Whether `name` is usable as a git branch name: no empty or hidden path
+/// components and none of the characters git reserves.
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with(".lock")
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+        && name
+            .split('/')
+            .all(|component| !component.is_empty() && !component.starts_with('.'))
+}
+
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for word in text
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        if slug.len() + word.len() + 1 > MAX_SLUG_LEN && !slug.is_empty() {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_ascii_lowercase());
+    }
+    slug
+}
@@ -0,0 +1,196 @@
+use anyhow::{bail, Result};
+use jj_lib::commit::Commit;
+
+use super::{resolve_revisions, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::config::CommitStandard;
+use crate::conventional::{parse_header, section_for, TYPES};
+use crate::diff::commit_patch;
+use crate::llm::write_changelog;
+
+/// Descriptions with fewer words than this are sent along with their diff.
+const THIN_DESCRIPTION_WORDS: usize = 4;
+
+const KEEP_A_CHANGELOG_SECTIONS: &[&str] =
+    &["Added", "Changed", "Deprecated", "Removed", "Fixed", "Security"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChangelogFormat {
+    /// A Markdown section, grouped by Conventional Commits type if configured
+    Markdown,
+    /// A section following https://keepachangelog.com
+    KeepAChangelog,
+}
+
+/// Writes a changelog section for the commits in `revision`.
+///
+/// `title` names the release; it defaults to "Unreleased".
+pub async fn run_changelog(
+    ctx: CommandContext,
+    revision: &str,
+    format: ChangelogFormat,
+    title: Option<&str>,
+) -> Result<String> {
+    let mut commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+    // Oldest first, so the model reads the history in order.
+    commits.reverse();
+
+    let conventional = matches!(ctx.cfg.standard(), CommitStandard::Conventional);
+    let sections: Vec<&str> = match format {
+        ChangelogFormat::KeepAChangelog => KEEP_A_CHANGELOG_SECTIONS.to_vec(),
+        ChangelogFormat::Markdown if conventional => {
+            let mut sections: Vec<&str> = TYPES.iter().map(|(_, section)| *section).collect();
+            sections.push("Other");
+            sections
+        }
+        ChangelogFormat::Markdown => vec!["Changes"],
+    };
+
+    let mut listed = Vec::new();
+    let mut thin = 0;
+    for commit in &commits {
+        let description = commit.description().trim();
+        if description.split_whitespace().count() >= THIN_DESCRIPTION_WORDS {
+            listed.push((commit, None));
+            continue;
+        }
+        let patch = commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
+        if description.is_empty() && patch.is_empty() {
+            continue;
+        }
+        thin += 1;
+        listed.push((commit, Some(patch)));
+    }
+    if listed.is_empty() {
+        bail!("no changes in '{revision}' to write a changelog for");
+    }
+
+    let mut headers = Vec::new();
+    for (commit, _) in &listed {
+        let description = commit.description().trim();
+        let mut header = format!("Commit {}", &commit.change_id().reverse_hex()[..8]);
+        if conventional {
+            if let Some(parsed) = description.lines().next().and_then(parse_header) {
+                header.push_str(&format!(" (type: {})", parsed.kind));
+            }
+        }
+        header.push('\n');
+        if description.is_empty() {
+            header.push_str("(no description)\n");
+        } else {
+            header.push_str(description);
+            header.push('\n');
+        }
+        headers.push(header);
+    }
+
+    // Diffs of thinly described commits share what is left of the budget.
+    let described_tokens: usize = headers.iter().map(|header| estimate_tokens(header)).sum();
+    let share = ctx.cfg.token_budget().saturating_sub(described_tokens) / thin.max(1);
+
+    let mut prompt = String::new();
+    for ((_, patch), header) in listed.iter().zip(&headers) {
+        prompt.push_str(header);
+        if let Some(patch) = patch {
+            prompt.push_str(&fit_patch(patch, share));
+        }
+        prompt.push('\n');
+    }
+
+    let mut entries =
+        write_changelog(ctx.provider.as_ref(), &ctx.cfg, &sections, &prompt).await?;
+
+    let change_ids: Vec<String> = listed
+        .iter()
+        .map(|(commit, _)| commit.change_id().reverse_hex())
+        .collect();
+
+    // Conventional types are authoritative: an entry whose commits all have
+    // types of the same section goes there, whatever the model chose.
+    if format == ChangelogFormat::Markdown && conventional {
+        for entry in &mut entries {
+            let type_sections: Vec<Option<&str>> = entry
+                .change_ids
+                .iter()
+                .filter_map(|id| find_change(&change_ids, id))
+                .map(|index| {
+                    listed[index]
+                        .0
+                        .description()
+                        .lines()
+                        .next()
+                        .and_then(parse_header)
+                        .and_then(|header| section_for(header.kind))
+                })
+                .collect();
+            if let Some(Some(section)) = type_sections.first() {
+                if type_sections.iter().all(|s| s == &Some(*section)) {
+                    entry.section = section.to_string();
+                }
+            }
+        }
+    }
+
+    let title = title.unwrap_or("Unreleased");
+    let mut output = match format {
+        ChangelogFormat::Markdown => format!("## {title}\n"),
+        ChangelogFormat::KeepAChangelog if title == "Unreleased" => format!("## [{title}]\n"),
+        ChangelogFormat::KeepAChangelog => format!("## [{title}] - {}\n", release_date(&commits)),
+    };
+
+    for section in &sections {
+        let entries: Vec<_> = entries
+            .iter()
+            .filter(|entry| {
+                // Entries in unknown sections are kept under the last one.
+                entry.section == *section
+                    || (section == sections.last().unwrap()
+                        && !sections.contains(&entry.section.as_str()))
+            })
+            .collect();
+        if entries.is_empty() {
+            continue;
+        }
+
+        output.push_str(&format!("\n### {section}\n\n"));
+        for entry in entries {
+            // Only cite ids of commits in the range.
+            let cited: Vec<&str> = entry
+                .change_ids
+                .iter()
+                .filter_map(|id| find_change(&change_ids, id))
+                .map(|index| &change_ids[index][..8])
+                .collect();
+            if cited.is_empty() {
+                output.push_str(&format!("- {}\n", entry.text.trim()));
+            } else {
+                output.push_str(&format!("- {} ({})\n", entry.text.trim(), cited.join(", ")));
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Finds the change id in `change_ids` that `id` is a prefix of.
+/// The date of the newest commit in the range, in its committer's time zone,
+/// so the heading is the same whenever it is generated.
+fn release_date(commits: &[Commit]) -> String {
+    commits
+        .iter()
+        .map(|commit| &commit.committer().timestamp)
+        .max_by_key(|timestamp| timestamp.timestamp)
+        .and_then(|timestamp| {
+            let offset = chrono::FixedOffset::east_opt(timestamp.tz_offset * 60)?;
+            let date = chrono::DateTime::from_timestamp_millis(timestamp.timestamp.0)?;
+            Some(date.with_timezone(&offset).format("%Y-%m-%d").to_string())
+        })
+        .unwrap_or_default()
+}
+
+fn find_change(change_ids: &[String], id: &str) -> Option<usize> {
+    if id.is_empty() {
+        return None;
+    }
+    change_ids.iter().position(|full| full.starts_with(id))
+}
@@ -1,77 +1,201 @@
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use jj_lib::backend::CommitId;
+use jj_lib::commit::Commit;
 use jj_lib::object_id::ObjectId;
 use jj_lib::repo::Repo;
-use jj_lib::repo_path::RepoPathUiConverter;
-use jj_lib::revset::{
-    self, RevsetAliasesMap, RevsetDiagnostics, RevsetExtensions, RevsetIteratorExt,
-    RevsetParseContext, RevsetWorkspaceContext, SymbolResolverExtension,
-};
-
-use super::CommandContext;
-use crate::diff::render_commit_patch;
+use jj_lib::trailer::Trailer;
+
+use super::{resolve_revisions, CommandContext};
+use crate::config::ConflictPolicy;
+use crate::diff::{commit_patch, CommitPatch};
 use crate::editor::edit_text;
-use crate::llm::generate_description_for_diff;
+use crate::lint::lint_description;
+use crate::llm::{generate_description_for_patch, improve_description, CommitContext};
+use crate::ticket::{extract_tickets, insert_tickets};
+use crate::trailer::{merge_trailers, split_trailers, MODEL_TRAILER};
 
 pub struct DescribedCommit {
     pub commit_id: String,
     pub change_id: String,
     pub description: String,
+    /// Paths with unresolved conflicts in the described commit.
+    pub conflicted_paths: Vec<String>,
+    /// The description that was improved, in `--improve` mode.
+    pub previous_description: Option<String>,
+}
+
+/// What `describe` does with commits that already have a description.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DescribeMode {
+    /// Leave them as they are.
+    #[default]
+    Fill,
+    /// Replace them with a new description.
+    Overwrite,
+    /// Fix them up to the configured standard, keeping their intent.
+    Improve,
 }
 
 pub struct DescribeResult {
     pub described: Vec<DescribedCommit>,
     pub applied: bool,
     pub skipped_existing: usize,
+    pub skipped_conflicted: usize,
 }
 
 pub async fn run_describe(
     ctx: CommandContext,
     revision: &str,
     dry_run: bool,
-    overwrite: bool,
+    mode: DescribeMode,
     editor: bool,
 ) -> Result<DescribeResult> {
     let commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
 
-    let mut described = Vec::new();
+    let stack_bookmarks = stack_bookmarks(ctx.repo.as_ref(), &commits);
+    let added_trailers = configured_trailers(&ctx);
+
+    // Descriptions of commits in the revset, used as context for their
+    // children. Commits that keep their description count as already chosen.
+    let mut chosen: HashMap<CommitId, String> = HashMap::new();
+    let mut pending = Vec::new();
     let mut skipped_existing = 0;
+    let mut skipped_conflicted = 0;
 
     for commit in &commits {
-        if !overwrite && !commit.description().trim().is_empty() {
+        let improve = mode == DescribeMode::Improve && !commit.description().trim().is_empty();
+        if mode == DescribeMode::Fill && !commit.description().trim().is_empty() {
+            chosen.insert(commit.id().clone(), commit.description().to_string());
             skipped_existing += 1;
             continue;
         }
 
-        let diff = render_commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
+        let conflicted_paths: Vec<String> = commit
+            .tree()
+            .conflicts()
+            .map(|(path, _)| path.as_internal_file_string().to_string())
+            .collect();
 
-        if diff.trim().is_empty() {
+        if !conflicted_paths.is_empty() && ctx.cfg.conflicts() == ConflictPolicy::Skip {
+            skipped_conflicted += 1;
             continue;
         }
 
-        let mut description = generate_description_for_diff(&ctx.cfg, &diff).await?;
+        let patch = commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
 
-        if editor {
-            match edit_text(&description)? {
-                Some(edited) => description = edited,
-                None => continue,
-            }
+        let merged_parents = if commit.parent_ids().len() > 1 {
+            merge_parent_summaries(ctx.repo.as_ref(), commit)?
+        } else {
+            Vec::new()
+        };
+
+        // A clean merge has an empty diff but still deserves a message.
+        if patch.is_empty() && merged_parents.is_empty() {
+            continue;
         }
 
-        described.push(DescribedCommit {
-            commit_id: commit.id().hex(),
-            change_id: commit.change_id().hex(),
-            description,
+        let tickets = match ctx.cfg.ticket_pattern() {
+            Some(pattern) => extract_tickets(pattern, &descendant_bookmarks(ctx.repo.as_ref(), commit)?),
+            None => Vec::new(),
+        };
+
+        let context = CommitContext {
+            conflicted_paths,
+            merged_parents,
+            bookmarks: stack_bookmarks.get(commit.id()).cloned().unwrap_or_default(),
+            tickets,
+            ..Default::default()
+        };
+        pending.push((commit, patch, context, improve));
+    }
+
+    // Describe the stack in topological waves: a commit is ready once none
+    // of its parents is still pending, so every prompt can include the
+    // descriptions chosen for its parents. Requests within a wave run
+    // concurrently, throttled by the provider. Without stack context
+    // everything goes in one wave.
+    let mut results: HashMap<CommitId, DescribedCommit> = HashMap::new();
+    while !pending.is_empty() {
+        let pending_ids: HashSet<CommitId> =
+            pending.iter().map(|(commit, ..)| commit.id().clone()).collect();
+        let (mut ready, waiting): (Vec<_>, Vec<_>) = pending.into_iter().partition(|(commit, ..)| {
+            !ctx.cfg.stack_context()
+                || !commit.parent_ids().iter().any(|id| pending_ids.contains(id))
         });
+        pending = waiting;
+
+        // Parents outside the revset contribute their current descriptions.
+        for (commit, _, context, _) in &mut ready {
+            context.parent_descriptions.clear();
+            for parent in commit.parents() {
+                let parent = parent.context("failed to load parent")?;
+                let description = chosen
+                    .get(parent.id())
+                    .map_or(parent.description(), String::as_str);
+                if !description.trim().is_empty() {
+                    context.parent_descriptions.push(description.to_string());
+                }
+            }
+        }
+
+        let descriptions = try_join_all(ready.iter().map(|(commit, patch, context, improve)| {
+            let ctx = &ctx;
+            async move {
+                if *improve {
+                    improve_existing(ctx, commit.description(), patch, context).await
+                } else {
+                    generate_description_for_patch(ctx.provider.as_ref(), &ctx.cfg, patch, context)
+                        .await
+                }
+            }
+        }))
+        .await?;
+
+        for ((commit, _, context, improve), mut description) in ready.into_iter().zip(descriptions)
+        {
+            // The model doesn't always follow the ticket instructions.
+            description = insert_tickets(
+                &description,
+                &context.tickets,
+                ctx.cfg.ticket_placement(),
+                ctx.cfg.standard(),
+            );
+            description = merge_trailers(commit.description(), &description, &added_trailers);
+            if editor {
+                match edit_text(&description)? {
+                    Some(edited) => description = edited,
+                    None => continue,
+                }
+            }
+
+            chosen.insert(commit.id().clone(), description.clone());
+            results.insert(
+                commit.id().clone(),
+                DescribedCommit {
+                    commit_id: commit.id().hex(),
+                    change_id: commit.change_id().reverse_hex(),
+                    description,
+                    conflicted_paths: context.conflicted_paths,
+                    previous_description: improve.then(|| commit.description().to_string()),
+                },
+            );
+        }
     }
 
+    let described: Vec<_> = commits
+        .iter()
+        .filter_map(|commit| results.remove(commit.id()))
+        .collect();
+
     if described.is_empty() || dry_run {
         return Ok(DescribeResult {
             described,
             applied: false,
             skipped_existing,
+            skipped_conflicted,
         });
     }
 
@@ -105,59 +229,120 @@ pub async fn run_describe(
         described,
         applied: true,
         skipped_existing,
+        skipped_conflicted,
     })
 }
 
-fn resolve_revisions(
-    repo: &Arc<jj_lib::repo::ReadonlyRepo>,
-    workspace: &jj_lib::workspace::Workspace,
-    revision: &str,
-) -> Result<Vec<jj_lib::commit::Commit>> {
-    let aliases_map = RevsetAliasesMap::new();
-    let extensions = RevsetExtensions::new();
-    let path_converter = RepoPathUiConverter::Fs {
-        cwd: std::env::current_dir().unwrap(),
-        base: workspace.workspace_root().to_owned(),
-    };
-    let workspace_ctx = RevsetWorkspaceContext {
-        path_converter: &path_converter,
-        workspace_name: workspace.workspace_name(),
-    };
-    let context = RevsetParseContext {
-        aliases_map: &aliases_map,
-        local_variables: HashMap::new(),
-        user_email: repo.settings().user_email(),
-        date_pattern_context: chrono::Utc::now().fixed_offset().into(),
-        default_ignored_remote: None,
-        use_glob_by_default: false,
-        extensions: &extensions,
-        workspace: Some(workspace_ctx),
-    };
-
-    let mut diagnostics = RevsetDiagnostics::new();
-    let expression = revset::parse(&mut diagnostics, revision, &context)
-        .with_context(|| format!("failed to parse revision '{revision}'"))?;
-
-    let symbol_extensions: &[Arc<dyn SymbolResolverExtension>] = &[];
-    let symbol_resolver = revset::SymbolResolver::new(repo.as_ref(), symbol_extensions);
-
-    let resolved = expression
-        .resolve_user_expression(repo.as_ref(), &symbol_resolver)
-        .with_context(|| format!("failed to resolve revision '{revision}'"))?;
-
-    let revset = resolved
-        .evaluate(repo.as_ref())
-        .with_context(|| format!("failed to evaluate revision '{revision}'"))?;
-
-    let commits: Vec<_> = revset
-        .iter()
-        .commits(repo.store())
-        .collect::<Result<_, _>>()
-        .context("failed to get commits")?;
+/// Asks the model to fix `description` up to the configured standard,
+/// keeping its intent. Trailers are left out, as they are merged back in
+/// afterwards.
+async fn improve_existing(
+    ctx: &CommandContext,
+    description: &str,
+    patch: &CommitPatch,
+    context: &CommitContext,
+) -> Result<String> {
+    let (text, _) = split_trailers(description);
+    let problems: Vec<String> = lint_description(ctx.cfg.standard(), text)
+        .into_iter()
+        .map(|violation| violation.message)
+        .collect();
+    improve_description(
+        ctx.provider.as_ref(),
+        &ctx.cfg,
+        text,
+        &problems,
+        patch,
+        context,
+    )
+    .await
+}
+
+/// Trailers to add to every generated description, in canonical order.
+fn configured_trailers(ctx: &CommandContext) -> Vec<Trailer> {
+    let mut trailers = ctx.cfg.extra_trailers().to_vec();
+    let settings = ctx.repo.settings();
+    if ctx.cfg.sign_off() {
+        trailers.push(Trailer {
+            key: "Signed-off-by".to_string(),
+            value: format!("{} <{}>", settings.user_name(), settings.user_email()),
+        });
+    }
+    if ctx.cfg.model_trailer() {
+        trailers.push(Trailer {
+            key: MODEL_TRAILER.to_string(),
+            value: ctx.cfg.model().to_string(),
+        });
+    }
+    trailers
+}
+
+/// Summarizes each parent of a merge commit as its change id, bookmarks and
+/// the first line of its description.
+fn merge_parent_summaries(repo: &dyn Repo, commit: &Commit) -> Result<Vec<String>> {
+    let mut summaries = Vec::new();
+    for parent in commit.parents() {
+        let parent = parent.context("failed to load parent")?;
+        let mut summary = parent.change_id().reverse_hex()[..8].to_string();
+
+        let bookmarks: Vec<_> = repo
+            .view()
+            .local_bookmarks_for_commit(parent.id())
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !bookmarks.is_empty() {
+            summary.push_str(&format!(" ({})", bookmarks.join(", ")));
+        }
 
-    if commits.is_empty() {
-        bail!("revision '{revision}' not found");
+        let title = parent.description().lines().next().unwrap_or_default();
+        if title.is_empty() {
+            summary.push_str(": (no description)");
+        } else {
+            summary.push_str(&format!(": {title}"));
+        }
+        summaries.push(summary);
     }
+    Ok(summaries)
+}
 
-    Ok(commits)
+/// Names of the local bookmarks pointing at `commit` or at one of its
+/// descendants.
+fn descendant_bookmarks(repo: &dyn Repo, commit: &Commit) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for (name, target) in repo.view().local_bookmarks() {
+        for id in target.added_ids() {
+            if repo
+                .index()
+                .is_ancestor(commit.id(), id)
+                .context("failed to query the index")?
+            {
+                names.push(name.as_str().to_string());
+                break;
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// Maps each commit to the bookmarks on it or on any of its descendants in
+/// `commits`, which are expected in reverse topological order.
+fn stack_bookmarks(repo: &dyn Repo, commits: &[Commit]) -> HashMap<CommitId, Vec<String>> {
+    let mut bookmarks: HashMap<CommitId, Vec<String>> = HashMap::new();
+    for commit in commits {
+        let mut names = bookmarks.remove(commit.id()).unwrap_or_default();
+        for (name, _) in repo.view().local_bookmarks_for_commit(commit.id()) {
+            names.push(name.as_str().to_string());
+        }
+        names.sort();
+        names.dedup();
+
+        for parent_id in commit.parent_ids() {
+            bookmarks
+                .entry(parent_id.clone())
+                .or_default()
+                .extend(names.iter().cloned());
+        }
+        bookmarks.insert(commit.id().clone(), names);
+    }
+    bookmarks
 }
@@ -0,0 +1,49 @@
+use anyhow::{bail, Result};
+
+use super::{parse_fileset, resolve_single_revision, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::diff::{commit_patch_with, PatchOptions};
+use crate::llm::explain_commit;
+
+/// Unchanged lines shown around each change, more than in a normal diff so
+/// the model sees the code the change sits in.
+const CONTEXT_LINES: usize = 10;
+
+/// Streams an explanation of the commit at `revision` to `on_text`,
+/// optionally only covering the files matched by `fileset`.
+pub async fn run_explain(
+    ctx: CommandContext,
+    revision: &str,
+    fileset: Option<&str>,
+    on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+) -> Result<()> {
+    let commit = resolve_single_revision(&ctx.repo, &ctx.workspace, revision)?;
+
+    let matcher = fileset
+        .map(|fileset| parse_fileset(&ctx.workspace, fileset))
+        .transpose()?;
+    let mut options = PatchOptions {
+        context_lines: CONTEXT_LINES,
+        ..Default::default()
+    };
+    if let Some(matcher) = &matcher {
+        options.matcher = matcher.as_ref();
+    }
+    let patch = commit_patch_with(ctx.repo.as_ref(), &commit, ctx.cfg.ignore(), &options).await?;
+    if patch.is_empty() {
+        match fileset {
+            Some(fileset) => bail!("commit has no changes in '{fileset}'"),
+            None => bail!("commit has no changes to explain"),
+        }
+    }
+
+    let mut prompt = String::new();
+    match commit.description().trim() {
+        "" => prompt.push_str("Description: (none)\n\n"),
+        description => prompt.push_str(&format!("Description:\n{description}\n\n")),
+    }
+    let budget = ctx.cfg.token_budget().saturating_sub(estimate_tokens(&prompt));
+    prompt.push_str(&fit_patch(&patch, budget));
+
+    explain_commit(ctx.provider.as_ref(), &ctx.cfg, &prompt, on_text).await
+}
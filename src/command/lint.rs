@@ -0,0 +1,118 @@
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use jj_lib::commit::Commit;
+
+use super::{resolve_revisions, CommandContext};
+use crate::diff::commit_patch;
+use crate::lint::{lint_description, Violation};
+use crate::llm::{improve_description, CommitContext};
+use crate::trailer::{merge_trailers, split_trailers};
+
+pub struct LintedCommit {
+    pub commit: Commit,
+    pub change_id: String,
+    pub violations: Vec<Violation>,
+}
+
+/// A rewritten description for a commit that failed linting.
+pub struct LintFix {
+    pub commit: Commit,
+    pub change_id: String,
+    pub description: String,
+    /// Violations the rewritten description still has.
+    pub remaining: Vec<Violation>,
+}
+
+/// Checks the descriptions in `revision` against the configured standard and
+/// returns the commits that break a rule, in revset order.
+///
+/// Commits without changes or a description, such as a fresh working-copy
+/// commit, are not checked.
+pub async fn run_lint(ctx: &CommandContext, revision: &str) -> Result<Vec<LintedCommit>> {
+    let commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+
+    let mut linted = Vec::new();
+    for commit in commits {
+        if commit.description().trim().is_empty()
+            && commit
+                .is_empty(ctx.repo.as_ref())
+                .context("failed to check if commit is empty")?
+        {
+            continue;
+        }
+        let violations = lint_description(ctx.cfg.standard(), commit.description());
+        if !violations.is_empty() {
+            linted.push(LintedCommit {
+                change_id: commit.change_id().reverse_hex(),
+                commit,
+                violations,
+            });
+        }
+    }
+    Ok(linted)
+}
+
+/// Asks the model to rewrite each failing description, then lints the
+/// rewrites again.
+///
+/// The model only sees the text above the trailers, which are put back
+/// unchanged afterwards.
+pub async fn propose_fixes(ctx: &CommandContext, linted: &[LintedCommit]) -> Result<Vec<LintFix>> {
+    let mut prompts = Vec::new();
+    for item in linted {
+        let patch = commit_patch(ctx.repo.as_ref(), &item.commit, ctx.cfg.ignore()).await?;
+        let (text, _) = split_trailers(item.commit.description());
+        let problems: Vec<String> = item.violations.iter().map(|v| v.message.clone()).collect();
+        prompts.push((text, problems, patch));
+    }
+
+    let context = CommitContext::default();
+    let descriptions = try_join_all(prompts.iter().map(|(text, problems, patch)| {
+        improve_description(
+            ctx.provider.as_ref(),
+            &ctx.cfg,
+            text,
+            problems,
+            patch,
+            &context,
+        )
+    }))
+    .await?;
+
+    Ok(linted
+        .iter()
+        .zip(descriptions)
+        .map(|(item, description)| {
+            let description = format!("{}\n", description.trim());
+            let description = merge_trailers(item.commit.description(), &description, &[]);
+            LintFix {
+                commit: item.commit.clone(),
+                change_id: item.change_id.clone(),
+                remaining: lint_description(ctx.cfg.standard(), &description),
+                description,
+            }
+        })
+        .collect())
+}
+
+/// Writes the rewritten descriptions in a single transaction.
+pub fn apply_fixes(ctx: CommandContext, fixes: &[LintFix]) -> Result<()> {
+    let mut tx = ctx.repo.start_transaction();
+    for fix in fixes {
+        let new_commit = tx
+            .repo_mut()
+            .rewrite_commit(&fix.commit)
+            .set_description(&fix.description)
+            .write()
+            .context("failed to write commit")?;
+        tx.repo_mut()
+            .set_rewritten_commit(fix.commit.id().clone(), new_commit.id().clone());
+    }
+
+    tx.repo_mut()
+        .rebase_descendants()
+        .context("failed to rebase descendants")?;
+    tx.commit("ai lint --fix")
+        .context("failed to commit transaction")?;
+    Ok(())
+}
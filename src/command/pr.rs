@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use super::{resolve_revisions, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::diff::commit_patch;
+use crate::llm::{write_pull_request, PullRequest};
+
+/// Where forges look for pull request templates, relative to the workspace
+/// root, in order of preference.
+const TEMPLATE_PATHS: &[&str] = &[
+    ".github/pull_request_template.md",
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    "PULL_REQUEST_TEMPLATE.md",
+    "pull_request_template.md",
+    "docs/pull_request_template.md",
+    "docs/PULL_REQUEST_TEMPLATE.md",
+];
+
+/// Writes a pull request title and body for the commits in `revision`.
+pub async fn run_pr(ctx: CommandContext, revision: &str) -> Result<PullRequest> {
+    let mut commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+    commits.reverse();
+
+    let mut listed = Vec::new();
+    for commit in &commits {
+        let patch = commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
+        if patch.is_empty() && commit.description().trim().is_empty() {
+            continue;
+        }
+        let mut header = format!("Commit {}\n", &commit.change_id().reverse_hex()[..8]);
+        match commit.description().trim() {
+            "" => header.push_str("(no description)\n"),
+            description => {
+                header.push_str(description);
+                header.push('\n');
+            }
+        }
+        listed.push((header, patch));
+    }
+    if listed.is_empty() {
+        bail!("no changes in '{revision}' to describe");
+    }
+
+    let template = read_template(ctx.workspace.workspace_root())?;
+
+    // Descriptions always go in full; diffs share the rest of the budget.
+    let used = listed
+        .iter()
+        .map(|(header, _)| estimate_tokens(header))
+        .sum::<usize>()
+        + template.as_deref().map(estimate_tokens).unwrap_or_default();
+    let share = ctx.cfg.token_budget().saturating_sub(used) / listed.len();
+
+    let mut prompt = String::new();
+    for (header, patch) in &listed {
+        prompt.push_str(header);
+        prompt.push_str(&fit_patch(patch, share));
+        prompt.push('\n');
+    }
+
+    write_pull_request(ctx.provider.as_ref(), &ctx.cfg, &prompt, template.as_deref()).await
+}
+
+fn read_template(workspace_root: &Path) -> Result<Option<String>> {
+    for relative in TEMPLATE_PATHS {
+        let path = workspace_root.join(relative);
+        if path.is_file() {
+            let template = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read PR template '{}'", path.display()))?;
+            return Ok(Some(template));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use futures::future::try_join_all;
+use jj_lib::backend::{CopyId, TreeValue};
+use jj_lib::commit::Commit;
+use jj_lib::conflicts::{
+    materialize_merge_result_to_bytes, materialize_tree_value, MaterializedTreeValue,
+};
+use jj_lib::merge::Merge;
+use jj_lib::merged_tree::MergedTreeBuilder;
+use jj_lib::object_id::ObjectId;
+use jj_lib::repo::Repo;
+use jj_lib::repo_path::RepoPathBuf;
+
+use super::{resolve_single_revision, CommandContext};
+use crate::budget::estimate_tokens;
+use crate::diff::{materialize_options, text_patch, FilePatch};
+use crate::llm::resolve_conflict;
+
+/// Proposed resolutions for the conflicts in a commit, computed by
+/// [`plan_resolve`] and written by [`apply_resolve`].
+pub struct ResolvePlan {
+    pub commit: Commit,
+    pub files: Vec<ResolvedFile>,
+    /// Conflicted paths that were left alone, with the reason.
+    pub skipped: Vec<(String, &'static str)>,
+}
+
+pub struct ResolvedFile {
+    pub path: RepoPathBuf,
+    pub content: String,
+    /// The resolution as a diff against the file with conflict markers.
+    pub patch: FilePatch,
+    executable: bool,
+    copy_id: CopyId,
+}
+
+/// Asks the model to resolve each conflicted text file in the commit at
+/// `revision`. Nothing is written until the plan is applied.
+pub async fn plan_resolve(ctx: &CommandContext, revision: &str) -> Result<ResolvePlan> {
+    let commit = resolve_single_revision(&ctx.repo, &ctx.workspace, revision)?;
+
+    let tree = commit.tree();
+    let conflicts: Vec<_> = tree.conflicts().collect();
+    if conflicts.is_empty() {
+        bail!("commit has no conflicts");
+    }
+
+    let store = ctx.repo.store();
+    let options = materialize_options(ctx.repo.settings())?;
+    let mut skipped = Vec::new();
+    let mut pending = Vec::new();
+    for (path, value) in conflicts {
+        let display = path.as_internal_file_string().to_string();
+        let value = value.with_context(|| format!("failed to read conflict at '{display}'"))?;
+        let materialized = materialize_tree_value(store, &path, value, tree.labels())
+            .await
+            .with_context(|| format!("failed to read conflict at '{display}'"))?;
+        let MaterializedTreeValue::FileConflict(file) = materialized else {
+            skipped.push((display, "not a conflict between files"));
+            continue;
+        };
+        if file.contents.iter().any(|content| content.contains(&0)) {
+            skipped.push((display, "binary file"));
+            continue;
+        }
+
+        let mut prompt = format!("File: {display}\n");
+        let terms =
+            file.contents
+                .removes()
+                .enumerate()
+                .map(|(i, content)| {
+                    (
+                        format!("Base {}", i + 1),
+                        file.labels.get_remove(i),
+                        content,
+                    )
+                })
+                .chain(file.contents.adds().enumerate().map(|(i, content)| {
+                    (format!("Side {}", i + 1), file.labels.get_add(i), content)
+                }));
+        for (name, label, content) in terms {
+            match label.filter(|label| !label.is_empty()) {
+                Some(label) => prompt.push_str(&format!("\n{name} ({label}):\n")),
+                None => prompt.push_str(&format!("\n{name}:\n")),
+            }
+            prompt.push_str(&format!("```\n{}", String::from_utf8_lossy(content)));
+            if !content.ends_with(b"\n") {
+                prompt.push('\n');
+            }
+            prompt.push_str("```\n");
+        }
+        if estimate_tokens(&prompt) > ctx.cfg.token_budget() {
+            skipped.push((display, "too large for the token budget"));
+            continue;
+        }
+
+        let markers = materialize_merge_result_to_bytes(&file.contents, &file.labels, &options);
+        // Models tend to drop the final newline; keep it if every side has one.
+        let trailing_newline = file.contents.adds().all(|content| content.ends_with(b"\n"));
+        pending.push((path, display, file, prompt, markers, trailing_newline));
+    }
+
+    let resolutions =
+        try_join_all(pending.iter().map(|(_, _, _, prompt, _, _)| {
+            resolve_conflict(ctx.provider.as_ref(), &ctx.cfg, prompt)
+        }))
+        .await?;
+
+    let mut files = Vec::new();
+    for ((path, display, file, _, markers, trailing_newline), mut content) in
+        pending.into_iter().zip(resolutions)
+    {
+        if content
+            .lines()
+            .any(|line| line.starts_with("<<<<<<<") || line.starts_with(">>>>>>>"))
+        {
+            skipped.push((display, "resolution still has conflict markers"));
+            continue;
+        }
+        if trailing_newline && !content.is_empty() && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        files.push(ResolvedFile {
+            patch: text_patch(&display, &markers, content.as_bytes()),
+            path,
+            content,
+            executable: file.executable.unwrap_or(false),
+            copy_id: file.copy_id.unwrap_or_else(CopyId::placeholder),
+        });
+    }
+
+    Ok(ResolvePlan {
+        commit,
+        files,
+        skipped,
+    })
+}
+
+/// Writes the resolved files into the commit and rebases its descendants.
+pub async fn apply_resolve(ctx: CommandContext, plan: ResolvePlan) -> Result<Commit> {
+    if plan.files.is_empty() {
+        bail!("no conflicts were resolved");
+    }
+
+    let store = ctx.repo.store();
+    let mut builder = MergedTreeBuilder::new(plan.commit.tree());
+    for file in &plan.files {
+        let id = store
+            .write_file(&file.path, &mut file.content.as_bytes())
+            .await
+            .context("failed to write resolved file")?;
+        builder.set_or_remove(
+            file.path.clone(),
+            Merge::normal(TreeValue::File {
+                id,
+                executable: file.executable,
+                copy_id: file.copy_id.clone(),
+            }),
+        );
+    }
+    let tree = builder
+        .write_tree()
+        .context("failed to write resolved tree")?;
+
+    let mut tx = ctx.repo.start_transaction();
+    let commit = tx
+        .repo_mut()
+        .rewrite_commit(&plan.commit)
+        .set_tree(tree)
+        .write()
+        .context("failed to write resolved commit")?;
+    tx.repo_mut()
+        .rebase_descendants()
+        .context("failed to rebase descendants")?;
+    tx.commit(format!("ai resolve {}", plan.commit.id().hex()))
+        .context("failed to commit transaction")?;
+
+    Ok(commit)
+}
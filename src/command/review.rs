@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use futures::future::try_join_all;
+use jj_lib::diff_presentation::unified::DiffLineType;
+use jj_lib::object_id::ObjectId;
+use serde::Serialize;
+use serde_json::json;
+
+use super::{resolve_revisions, CommandContext};
+use crate::budget::estimate_tokens;
+use crate::diff::{commit_patch, render_line, CommitPatch, FilePatch, Hunk};
+use crate::llm::{review_diff, Finding, Severity};
+
+#[derive(Serialize)]
+pub struct ReviewedCommit {
+    pub commit_id: String,
+    pub change_id: String,
+    pub title: String,
+    #[serde(skip)]
+    pub patch: CommitPatch,
+    pub findings: Vec<Finding>,
+}
+
+/// Reviews every commit in `revision` separately, in revset order.
+pub async fn run_review(ctx: CommandContext, revision: &str) -> Result<Vec<ReviewedCommit>> {
+    let commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+
+    let mut patches = Vec::new();
+    for commit in &commits {
+        let patch = commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?;
+        if !patch.is_empty() {
+            patches.push((commit, patch));
+        }
+    }
+
+    let diffs: Vec<String> = patches
+        .iter()
+        .map(|(_, patch)| numbered_diff(patch, ctx.cfg.token_budget()))
+        .collect();
+    let findings = try_join_all(
+        diffs
+            .iter()
+            .map(|diff| review_diff(ctx.provider.as_ref(), &ctx.cfg, diff)),
+    )
+    .await?;
+
+    Ok(patches
+        .into_iter()
+        .zip(findings)
+        .map(|((commit, patch), mut findings)| {
+            findings.sort_by(|a, b| {
+                (&a.path, a.start_line, a.end_line).cmp(&(&b.path, b.start_line, b.end_line))
+            });
+            ReviewedCommit {
+                commit_id: commit.id().hex(),
+                change_id: commit.change_id().reverse_hex(),
+                title: commit.description().lines().next().unwrap_or_default().to_string(),
+                patch,
+                findings,
+            }
+        })
+        .collect())
+}
+
+/// Renders `patch` with new-tree line numbers in front of every line so the
+/// model can refer to them. Files that don't fit in `budget` are only listed.
+fn numbered_diff(patch: &CommitPatch, budget: usize) -> String {
+    let mut output = String::new();
+    let mut omitted = Vec::new();
+    for file in &patch.files {
+        let mut rendered = String::new();
+        for line in &file.header {
+            rendered.push_str(line);
+            rendered.push('\n');
+        }
+        for hunk in &file.hunks {
+            rendered.push_str(&hunk.header());
+            for (new_line, (line_type, content)) in numbered_lines(hunk) {
+                let number = new_line.map(|n| n.to_string()).unwrap_or_default();
+                rendered.push_str(&format!("{number:>5} {}", render_line(*line_type, content)));
+            }
+        }
+
+        if estimate_tokens(&output) + estimate_tokens(&rendered) > budget {
+            omitted.push(file.stat());
+        } else {
+            output.push_str(&rendered);
+        }
+    }
+
+    if !omitted.is_empty() {
+        output.push_str("\nThese files were left out to fit the model's context:\n");
+        for stat in omitted {
+            output.push_str(&stat);
+            output.push('\n');
+        }
+    }
+    output
+}
+
+/// Pairs each line of `hunk` with its line number in the new tree, if any.
+fn numbered_lines(hunk: &Hunk) -> impl Iterator<Item = (Option<usize>, &(DiffLineType, String))> {
+    let mut next = hunk.new_start;
+    hunk.lines.iter().map(move |line| match line.0 {
+        DiffLineType::Removed => (None, line),
+        DiffLineType::Context | DiffLineType::Added => {
+            next += 1;
+            (Some(next - 1), line)
+        }
+    })
+}
+
+/// Renders the diff of every file with findings, inserting each finding
+/// below the lines it refers to. Findings outside of the diff are listed
+/// after it.
+pub fn render_annotated(reviews: &[ReviewedCommit]) -> String {
+    let mut output = String::new();
+    for review in reviews {
+        let title = if review.title.is_empty() {
+            "(no description)"
+        } else {
+            &review.title
+        };
+        output.push_str(&format!("=== {} {title} ===\n", &review.change_id[..8]));
+        if review.findings.is_empty() {
+            output.push_str("No findings.\n\n");
+            continue;
+        }
+
+        let mut placed = HashSet::new();
+        for file in &review.patch.files {
+            let findings: Vec<(usize, &Finding)> = review
+                .findings
+                .iter()
+                .enumerate()
+                .filter(|(_, finding)| finding.path == file.path)
+                .collect();
+            if findings.is_empty() {
+                continue;
+            }
+            render_annotated_file(&mut output, file, &findings, &mut placed);
+        }
+
+        let unplaced: Vec<_> = review
+            .findings
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !placed.contains(index))
+            .collect();
+        for (_, finding) in unplaced {
+            output.push_str(&format!(
+                "{}:{}-{}: {}\n",
+                finding.path,
+                finding.start_line,
+                finding.end_line,
+                annotation(finding)
+            ));
+        }
+        output.push('\n');
+    }
+    output
+}
+
+fn render_annotated_file(
+    output: &mut String,
+    file: &FilePatch,
+    findings: &[(usize, &Finding)],
+    placed: &mut HashSet<usize>,
+) {
+    for line in &file.header {
+        output.push_str(line);
+        output.push('\n');
+    }
+    for hunk in &file.hunks {
+        let new_range = hunk.new_start..hunk.new_start + hunk.new_len;
+        output.push_str(&hunk.header());
+        for (new_line, (line_type, content)) in numbered_lines(hunk) {
+            output.push_str(&render_line(*line_type, content));
+            let Some(new_line) = new_line else {
+                continue;
+            };
+            // Findings go below their last line, or below the last line of
+            // the first hunk they overlap if they extend past it.
+            for &(index, finding) in findings {
+                let overlaps =
+                    finding.start_line < new_range.end && finding.end_line >= new_range.start;
+                let anchor = finding.end_line.min(new_range.end - 1);
+                if overlaps && new_line == anchor && !placed.contains(&index) {
+                    output.push_str(&format!("    ^ {}\n", annotation(finding)));
+                    placed.insert(index);
+                }
+            }
+        }
+    }
+}
+
+fn annotation(finding: &Finding) -> String {
+    let severity = match finding.severity {
+        Severity::Info => "info",
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    };
+    format!("[{severity}] {}", finding.message)
+}
+
+/// The reviews as a JSON array, one entry per commit.
+pub fn to_json(reviews: &[ReviewedCommit]) -> serde_json::Value {
+    serde_json::to_value(reviews).unwrap_or_default()
+}
+
+/// The reviews as a SARIF 2.1.0 log for code scanning tools.
+pub fn to_sarif(reviews: &[ReviewedCommit]) -> serde_json::Value {
+    let results: Vec<_> = reviews
+        .iter()
+        .flat_map(|review| {
+            review.findings.iter().map(move |finding| {
+                let level = match finding.severity {
+                    Severity::Info => "note",
+                    Severity::Warning => "warning",
+                    Severity::Error => "error",
+                };
+                json!({
+                    "ruleId": "ai-review",
+                    "level": level,
+                    "message": { "text": finding.message },
+                    "locations": [{
+                        "physicalLocation": {
+                            "artifactLocation": { "uri": finding.path },
+                            // SARIF requires 1 <= startLine <= endLine.
+                            "region": {
+                                "startLine": finding.start_line.max(1),
+                                "endLine": finding.end_line.max(finding.start_line.max(1)),
+                            },
+                        },
+                    }],
+                    "properties": {
+                        "changeId": review.change_id,
+                        "commitId": review.commit_id,
+                    },
+                })
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "jj-ai",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": [{
+                        "id": "ai-review",
+                        "shortDescription": { "text": "Finding from an AI code review" },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    })
+}
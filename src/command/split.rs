@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Context, Result};
+use jj_lib::backend::TreeValue;
+use jj_lib::commit::Commit;
+use jj_lib::conflicts::{materialize_tree_value, MaterializedTreeValue};
+use jj_lib::diff_presentation::unified::DiffLineType;
+use jj_lib::merge::Merge;
+use jj_lib::merged_tree::{MergedTree, MergedTreeBuilder};
+use jj_lib::repo::Repo;
+use jj_lib::repo_path::RepoPathBuf;
+use jj_lib::rewrite::merge_commit_trees;
+
+use super::{resolve_single_revision, CommandContext};
+use crate::diff::{commit_patch, CommitPatch, FilePatch, Hunk};
+use crate::llm::propose_split;
+
+/// The part of one file's diff that goes into a commit of a split.
+pub struct SplitChange {
+    pub path: String,
+    /// Indexes of the hunks taken from the file, or `None` for all of it.
+    pub hunks: Option<Vec<usize>>,
+}
+
+pub struct SplitCommit {
+    pub description: String,
+    pub changes: Vec<SplitChange>,
+}
+
+/// A proposed split of one commit into a series of commits, oldest first.
+pub struct SplitPlan {
+    pub commit: Commit,
+    pub commits: Vec<SplitCommit>,
+    patch: CommitPatch,
+    /// For every file in `patch`, the commit each of its units goes into.
+    /// Files in `bases` have one unit per hunk, other files a single unit.
+    assignments: Vec<Vec<usize>>,
+    /// Parent content of the files that can be split by hunk.
+    bases: HashMap<String, Vec<u8>>,
+}
+
+/// Asks the model how to split the commit at `revision`.
+///
+/// Changes the model leaves out go into the last commit, as do files
+/// excluded by `ai.ignore`.
+pub async fn plan_split(ctx: &CommandContext, revision: &str) -> Result<SplitPlan> {
+    let commit = resolve_single_revision(&ctx.repo, &ctx.workspace, revision)?;
+
+    if commit.tree().conflicts().next().is_some() {
+        bail!("cannot split a commit with unresolved conflicts");
+    }
+
+    let patch = commit_patch(ctx.repo.as_ref(), &commit, ctx.cfg.ignore()).await?;
+    if patch.is_empty() {
+        bail!("commit has no changes to split");
+    }
+
+    let parent_tree = parent_tree(ctx.repo.as_ref(), &commit).await?;
+    let mut bases = HashMap::new();
+    for file in &patch.files {
+        if let Some(base) = hunk_base(&parent_tree, &commit.tree(), file).await? {
+            bases.insert(file.path.clone(), base);
+        }
+    }
+
+    let groups = propose_split(
+        ctx.provider.as_ref(),
+        &ctx.cfg,
+        &labeled_diff(&patch, &bases),
+    )
+    .await?;
+    if groups.is_empty() {
+        bail!("the model proposed no commits");
+    }
+
+    let mut assignments: Vec<Vec<Option<usize>>> = patch
+        .files
+        .iter()
+        .map(|file| vec![None; unit_count(file, &bases)])
+        .collect();
+
+    for (index, group) in groups.iter().enumerate() {
+        for label in &group.changes {
+            let label = label.trim().trim_start_matches('[').trim_end_matches(']');
+            let (file_index, hunk) = resolve_label(&patch, &bases, label)
+                .with_context(|| format!("the model referenced an unknown change '{label}'"))?;
+            let units = &mut assignments[file_index];
+            match hunk {
+                Some(hunk) => {
+                    units[hunk].get_or_insert(index);
+                }
+                None => units.iter_mut().for_each(|unit| {
+                    unit.get_or_insert(index);
+                }),
+            }
+        }
+    }
+
+    // Drop commits that ended up empty and renumber the rest.
+    let last = groups.len() - 1;
+    let assignments: Vec<Vec<usize>> = assignments
+        .into_iter()
+        .map(|units| units.into_iter().map(|unit| unit.unwrap_or(last)).collect())
+        .collect();
+    let mut kept: Vec<usize> = assignments.iter().flatten().copied().collect();
+    kept.sort();
+    kept.dedup();
+    let assignments = assignments
+        .into_iter()
+        .map(|units| {
+            units
+                .into_iter()
+                .map(|unit| kept.binary_search(&unit).unwrap())
+                .collect()
+        })
+        .collect::<Vec<Vec<usize>>>();
+
+    let commits = kept
+        .iter()
+        .enumerate()
+        .map(|(index, &group)| SplitCommit {
+            description: groups[group].description.clone(),
+            changes: patch
+                .files
+                .iter()
+                .zip(&assignments)
+                .filter_map(|(file, units)| {
+                    let hunks: Vec<usize> = (0..units.len()).filter(|&u| units[u] == index).collect();
+                    if hunks.is_empty() {
+                        None
+                    } else {
+                        Some(SplitChange {
+                            path: file.path.clone(),
+                            hunks: (hunks.len() < units.len()).then_some(hunks),
+                        })
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    Ok(SplitPlan {
+        commit,
+        commits,
+        patch,
+        assignments,
+        bases,
+    })
+}
+
+/// Rewrites the commit into the planned series in a single transaction.
+///
+/// The first commit keeps the change id; descendants, bookmarks and the
+/// working copy move to the last one, whose tree is the original tree.
+pub async fn apply_split(ctx: CommandContext, plan: SplitPlan) -> Result<Vec<Commit>> {
+    if plan.commits.len() < 2 {
+        bail!("the plan has a single commit, nothing to split");
+    }
+
+    let store = ctx.repo.store().clone();
+    let parent_tree = parent_tree(ctx.repo.as_ref(), &plan.commit).await?;
+    let commit_tree = plan.commit.tree();
+
+    let mut trees = Vec::new();
+    for index in 0..plan.commits.len() - 1 {
+        let mut builder = MergedTreeBuilder::new(parent_tree.clone());
+        for (file, units) in plan.patch.files.iter().zip(&plan.assignments) {
+            let taken: Vec<usize> = (0..units.len()).filter(|&u| units[u] <= index).collect();
+            if taken.is_empty() {
+                continue;
+            }
+
+            let path = RepoPathBuf::from_internal_string(&file.path)?;
+            if taken.len() == units.len() {
+                builder.set_or_remove(path.clone(), commit_tree.path_value(&path)?);
+                if file.source != file.path {
+                    let source = RepoPathBuf::from_internal_string(&file.source)?;
+                    builder.set_or_remove(source.clone(), commit_tree.path_value(&source)?);
+                }
+                continue;
+            }
+
+            let content = apply_hunks(
+                &plan.bases[&file.path],
+                taken.iter().map(|&hunk| &file.hunks[hunk]),
+            );
+            let after = commit_tree.path_value(&path)?;
+            let Some(TreeValue::File {
+                executable,
+                copy_id,
+                ..
+            }) = after.as_normal()
+            else {
+                bail!("'{}' is no longer a regular file", file.path);
+            };
+            let id = store
+                .write_file(&path, &mut content.as_slice())
+                .await
+                .context("failed to write file")?;
+            builder.set_or_remove(
+                path,
+                Merge::normal(TreeValue::File {
+                    id,
+                    executable: *executable,
+                    copy_id: copy_id.clone(),
+                }),
+            );
+        }
+        trees.push(builder.write_tree().context("failed to write tree")?);
+    }
+    trees.push(commit_tree);
+
+    let mut tx = ctx.repo.start_transaction();
+    let mut new_commits: Vec<Commit> = Vec::new();
+    for (split, tree) in plan.commits.iter().zip(trees) {
+        let new_commit = match new_commits.last() {
+            None => tx
+                .repo_mut()
+                .rewrite_commit(&plan.commit)
+                .set_tree(tree)
+                .set_description(&split.description)
+                .write(),
+            Some(parent) => tx
+                .repo_mut()
+                .new_commit(vec![parent.id().clone()], tree)
+                .set_author(plan.commit.author().clone())
+                .set_description(&split.description)
+                .write(),
+        }
+        .context("failed to write commit")?;
+        new_commits.push(new_commit);
+    }
+
+    let last = new_commits.last().unwrap();
+    tx.repo_mut()
+        .set_rewritten_commit(plan.commit.id().clone(), last.id().clone());
+    tx.repo_mut()
+        .rebase_descendants()
+        .context("failed to rebase descendants")?;
+
+    tx.commit("ai split")
+        .context("failed to commit transaction")?;
+
+    Ok(new_commits)
+}
+
+async fn parent_tree(repo: &dyn Repo, commit: &Commit) -> Result<MergedTree> {
+    let parents: Vec<_> = commit
+        .parents()
+        .collect::<Result<_, _>>()
+        .context("failed to load parents")?;
+    merge_commit_trees(repo, &parents)
+        .await
+        .context("failed to merge parent trees")
+}
+
+/// Returns the parent content of `file` if its hunks can be applied
+/// independently: a modified regular text file with more than one hunk.
+async fn hunk_base(
+    parent_tree: &MergedTree,
+    commit_tree: &MergedTree,
+    file: &FilePatch,
+) -> Result<Option<Vec<u8>>> {
+    if file.source != file.path || file.hunks.len() < 2 {
+        return Ok(None);
+    }
+
+    let path = RepoPathBuf::from_internal_string(&file.path)?;
+    let before = parent_tree.path_value(&path)?;
+    let after = commit_tree.path_value(&path)?;
+    match (before.as_normal(), after.as_normal()) {
+        (
+            Some(TreeValue::File {
+                executable: old, ..
+            }),
+            Some(TreeValue::File {
+                executable: new, ..
+            }),
+        ) if old == new => {}
+        _ => return Ok(None),
+    }
+
+    let before = read_file(parent_tree, &path, before).await?;
+    let after = read_file(commit_tree, &path, after).await?;
+    // Hunk lines are decoded lossily, so only UTF-8 files round-trip.
+    if std::str::from_utf8(&before).is_err() || std::str::from_utf8(&after).is_err() {
+        return Ok(None);
+    }
+    Ok(Some(before))
+}
+
+async fn read_file(
+    tree: &MergedTree,
+    path: &RepoPathBuf,
+    value: jj_lib::merge::MergedTreeValue,
+) -> Result<Vec<u8>> {
+    match materialize_tree_value(tree.store(), path, value, tree.labels())
+        .await
+        .context("failed to read file content")?
+    {
+        MaterializedTreeValue::File(mut file) => {
+            Ok(file.read_all(path).await.context("failed to read file bytes")?)
+        }
+        _ => bail!("'{}' is not a regular file", path.as_internal_file_string()),
+    }
+}
+
+fn unit_count(file: &FilePatch, bases: &HashMap<String, Vec<u8>>) -> usize {
+    if bases.contains_key(&file.path) {
+        file.hunks.len()
+    } else {
+        1
+    }
+}
+
+/// Renders `patch` with a `[path]` label before every file, or a
+/// `[path#n]` label before every hunk of files that can be split by hunk.
+fn labeled_diff(patch: &CommitPatch, bases: &HashMap<String, Vec<u8>>) -> String {
+    let mut output = String::new();
+    for file in &patch.files {
+        if !bases.contains_key(&file.path) {
+            output.push_str(&format!("[{}]\n{file}", file.path));
+            continue;
+        }
+        for line in &file.header {
+            output.push_str(line);
+            output.push('\n');
+        }
+        for (index, hunk) in file.hunks.iter().enumerate() {
+            output.push_str(&format!("[{}#{}]\n{hunk}", file.path, index + 1));
+        }
+    }
+    output
+}
+
+/// Resolves a label to a file index and, for hunk labels, a hunk index.
+fn resolve_label(
+    patch: &CommitPatch,
+    bases: &HashMap<String, Vec<u8>>,
+    label: &str,
+) -> Option<(usize, Option<usize>)> {
+    if let Some(index) = patch.files.iter().position(|file| file.path == label) {
+        return Some((index, None));
+    }
+
+    let (path, hunk) = label.rsplit_once('#')?;
+    let hunk: usize = hunk.parse().ok()?;
+    let index = patch.files.iter().position(|file| file.path == path)?;
+    if !bases.contains_key(path) || hunk == 0 || hunk > patch.files[index].hunks.len() {
+        return None;
+    }
+    Some((index, Some(hunk - 1)))
+}
+
+/// Applies `hunks`, which must be in order, to `base`.
+fn apply_hunks<'a>(base: &[u8], hunks: impl IntoIterator<Item = &'a Hunk>) -> Vec<u8> {
+    let lines: Vec<&[u8]> = base.split_inclusive(|&b| b == b'\n').collect();
+    let mut output = Vec::new();
+    let mut next = 0;
+    for hunk in hunks {
+        let start = hunk.old_start - 1;
+        lines[next..start]
+            .iter()
+            .for_each(|line| output.extend_from_slice(line));
+        for (line_type, content) in &hunk.lines {
+            if *line_type != DiffLineType::Removed {
+                output.extend_from_slice(content.as_bytes());
+            }
+        }
+        next = start + hunk.old_len;
+    }
+    lines[next..]
+        .iter()
+        .for_each(|line| output.extend_from_slice(line));
+    output
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+
+use anyhow::{bail, Context, Result};
+use jj_lib::commit::Commit;
+use jj_lib::diff_presentation::unified::DiffLineType;
+use jj_lib::object_id::ObjectId;
+use jj_lib::repo::Repo;
+use jj_lib::rewrite::{merge_commit_trees, squash_commits, CommitWithSelection};
+
+use super::{resolve_revisions, CommandContext};
+use crate::budget::{estimate_tokens, fit_patch};
+use crate::diff::{commit_patch, CommitPatch, Hunk};
+use crate::llm::suggest_squashes;
+
+/// Subject prefixes of commits meant to be squashed into the earlier commit
+/// with the rest of the subject, as with `git commit --fixup`.
+const FIXUP_PREFIXES: &[&str] = &["fixup! ", "squash! ", "amend! "];
+
+/// A commit to fold into an earlier commit of the same stack.
+pub struct Squash {
+    pub source: Commit,
+    pub target: Commit,
+    pub reason: String,
+}
+
+/// Proposed squashes, computed by [`plan_squashes`] and applied by
+/// [`apply_squashes`], with sources oldest first.
+pub struct SquashPlan {
+    pub squashes: Vec<Squash>,
+}
+
+struct Candidate {
+    source: usize,
+    target: usize,
+    evidence: String,
+}
+
+/// Finds commits in `revision` whose changes belong in an earlier commit.
+///
+/// Commits with a `fixup!` subject go into the commit they name. For the
+/// rest, a commit is a candidate for an earlier ancestor in the set if it
+/// only touches files that ancestor touched or changes lines it changed; the
+/// model then picks which candidates are fixups.
+pub async fn plan_squashes(ctx: &CommandContext, revision: &str) -> Result<SquashPlan> {
+    let mut commits = resolve_revisions(&ctx.repo, &ctx.workspace, revision)?;
+    commits.reverse();
+
+    let mut patches = Vec::new();
+    for commit in &commits {
+        patches.push(commit_patch(ctx.repo.as_ref(), commit, ctx.cfg.ignore()).await?);
+    }
+    let index = ctx.repo.index();
+    let is_ancestor = |target: usize, source: usize| -> Result<bool> {
+        index
+            .is_ancestor(commits[target].id(), commits[source].id())
+            .context("failed to query the index")
+    };
+
+    let mut chosen: Vec<(usize, usize, String)> = Vec::new();
+    let mut candidates = Vec::new();
+    for source in 0..commits.len() {
+        let commit = &commits[source];
+        if commit.parent_ids().len() > 1 || patches[source].is_empty() {
+            continue;
+        }
+
+        let subject = commit.description().lines().next().unwrap_or_default();
+        if let Some(title) = FIXUP_PREFIXES
+            .iter()
+            .find_map(|prefix| subject.strip_prefix(prefix))
+        {
+            let mut target = None;
+            for earlier in (0..source).rev() {
+                let earlier_subject = commits[earlier].description().lines().next();
+                if earlier_subject == Some(title.trim()) && is_ancestor(earlier, source)? {
+                    target = Some(earlier);
+                    break;
+                }
+            }
+            if let Some(target) = target {
+                chosen.push((source, target, format!("marked as a fixup of \"{}\"", title.trim())));
+                continue;
+            }
+        }
+
+        let files: BTreeSet<&str> = patches[source].files.iter().map(|f| f.path.as_str()).collect();
+        for target in (0..source).rev() {
+            if patches[target].is_empty() || !is_ancestor(target, source)? {
+                continue;
+            }
+            let target_files: BTreeSet<&str> =
+                patches[target].files.iter().map(|f| f.path.as_str()).collect();
+            let shared: Vec<&str> = files.intersection(&target_files).copied().collect();
+            if shared.is_empty() {
+                continue;
+            }
+            let overlapping = overlapping_hunks(&patches[target], &patches[source]);
+            if overlapping == 0 && shared.len() < files.len() {
+                continue;
+            }
+
+            let mut evidence = format!("touches {}", shared.join(", "));
+            if shared.len() == files.len() {
+                evidence.push_str(", all of which the target touched");
+            }
+            if overlapping > 0 {
+                evidence.push_str(&format!("; {overlapping} hunk(s) change lines the target changed"));
+            }
+            candidates.push(Candidate {
+                source,
+                target,
+                evidence,
+            });
+        }
+    }
+
+    if !candidates.is_empty() {
+        let short_id = |index: usize| commits[index].change_id().reverse_hex()[..8].to_string();
+        let listed: BTreeSet<usize> = candidates
+            .iter()
+            .flat_map(|c| [c.source, c.target])
+            .collect();
+
+        let mut prompt = String::from("Candidate squashes:\n");
+        for candidate in &candidates {
+            prompt.push_str(&format!(
+                "- {} into {}: {}\n",
+                short_id(candidate.source),
+                short_id(candidate.target),
+                candidate.evidence
+            ));
+        }
+        prompt.push('\n');
+
+        let share = ctx.cfg.token_budget().saturating_sub(estimate_tokens(&prompt)) / listed.len();
+        for &index in &listed {
+            let mut entry = format!("Commit {}\n", short_id(index));
+            match commits[index].description().trim() {
+                "" => entry.push_str("(no description)\n"),
+                description => {
+                    entry.push_str(description);
+                    entry.push('\n');
+                }
+            }
+            let budget = share.saturating_sub(estimate_tokens(&entry));
+            entry.push_str(&fit_patch(&patches[index], budget));
+            entry.push('\n');
+            prompt.push_str(&entry);
+        }
+
+        let suggestions = suggest_squashes(ctx.provider.as_ref(), &ctx.cfg, &prompt).await?;
+        // Suggestions outside of the candidates are dropped, as are repeated
+        // sources: the model's judgement only narrows down the evidence.
+        for suggestion in suggestions {
+            let Some(candidate) = candidates.iter().find(|c| {
+                matches_id(&commits[c.source], &suggestion.source)
+                    && matches_id(&commits[c.target], &suggestion.target)
+            }) else {
+                continue;
+            };
+            if chosen.iter().any(|(source, _, _)| *source == candidate.source) {
+                continue;
+            }
+            chosen.push((candidate.source, candidate.target, suggestion.reason));
+        }
+    }
+
+    // A target that is itself squashed away is replaced by its own target.
+    let targets: HashMap<usize, usize> = chosen.iter().map(|(s, t, _)| (*s, *t)).collect();
+    for (_, target, _) in &mut chosen {
+        while let Some(next) = targets.get(target) {
+            *target = *next;
+        }
+    }
+    chosen.sort_by_key(|(source, _, _)| *source);
+
+    Ok(SquashPlan {
+        squashes: chosen
+            .into_iter()
+            .map(|(source, target, reason)| Squash {
+                source: commits[source].clone(),
+                target: commits[target].clone(),
+                reason,
+            })
+            .collect(),
+    })
+}
+
+/// Squashes each source into its target in a single transaction and rebases
+/// the descendants. Returns the rewritten targets.
+pub async fn apply_squashes(ctx: CommandContext, plan: SquashPlan) -> Result<Vec<Commit>> {
+    if plan.squashes.is_empty() {
+        bail!("nothing to squash");
+    }
+
+    // Sources are squashed together per target, oldest target first.
+    let mut groups: Vec<(&Commit, Vec<&Commit>)> = Vec::new();
+    for squash in &plan.squashes {
+        match groups.iter_mut().find(|(target, _)| target.id() == squash.target.id()) {
+            Some((_, sources)) => sources.push(&squash.source),
+            None => groups.push((&squash.target, vec![&squash.source])),
+        }
+    }
+
+    let mut tx = ctx.repo.start_transaction();
+    let mut rewritten = Vec::new();
+    for (target, sources) in groups {
+        // Earlier squashes rewrite later commits, so look up their current
+        // versions by change id.
+        let target = current_commit(tx.repo(), target)?;
+        let mut selections = Vec::new();
+        for source in sources {
+            let source = current_commit(tx.repo(), source)?;
+            let parents: Vec<Commit> = source
+                .parents()
+                .collect::<Result<_, _>>()
+                .context("failed to load parents")?;
+            let parent_tree = merge_commit_trees(tx.repo(), &parents)
+                .await
+                .context("failed to merge parent trees")?;
+            selections.push(CommitWithSelection {
+                selected_tree: source.tree(),
+                parent_tree,
+                commit: source,
+            });
+        }
+
+        let Some(squashed) = squash_commits(tx.repo_mut(), &selections, &target, false)
+            .context("failed to squash commits")?
+        else {
+            continue;
+        };
+        let mut builder = squashed.commit_builder;
+        if target.description().trim().is_empty() {
+            let descriptions: Vec<&str> = selections
+                .iter()
+                .map(|s| s.commit.description().trim())
+                .filter(|d| !d.is_empty())
+                .collect();
+            builder = builder.set_description(descriptions.join("\n\n"));
+        }
+        let commit = builder.write().context("failed to write squashed commit")?;
+        if commit.has_conflict() && !target.has_conflict() {
+            bail!(
+                "squashing into {} would create conflicts",
+                &target.change_id().reverse_hex()[..8]
+            );
+        }
+        tx.repo_mut()
+            .rebase_descendants()
+            .context("failed to rebase descendants")?;
+        rewritten.push(commit);
+    }
+
+    tx.commit("ai squash-suggest")
+        .context("failed to commit transaction")?;
+    Ok(rewritten)
+}
+
+/// The visible commit with the change id of `commit`.
+fn current_commit(repo: &dyn Repo, commit: &Commit) -> Result<Commit> {
+    let id = repo
+        .resolve_change_id(commit.change_id())
+        .context("failed to query the index")?
+        .and_then(|targets| targets.visible_with_offsets().next().map(|(_, id)| id.clone()))
+        .with_context(|| {
+            format!(
+                "change {} is no longer visible",
+                &commit.change_id().reverse_hex()[..8]
+            )
+        })?;
+    repo.store().get_commit(&id).context("failed to load commit")
+}
+
+/// Whether `id` is a prefix of the change id or commit id of `commit`.
+fn matches_id(commit: &Commit, id: &str) -> bool {
+    !id.is_empty()
+        && (commit.change_id().reverse_hex().starts_with(id) || commit.id().hex().starts_with(id))
+}
+
+/// How many hunks of `source` change lines that `target` changed. Only the
+/// added and removed lines count, not the context around them. Line numbers
+/// are compared as is, which holds as long as the commits in between don't
+/// move the lines.
+fn overlapping_hunks(target: &CommitPatch, source: &CommitPatch) -> usize {
+    let mut overlapping = 0;
+    for file in &source.files {
+        let Some(target_file) = target.files.iter().find(|f| f.path == file.source) else {
+            continue;
+        };
+        let target_changes: Vec<Range<usize>> = target_file
+            .hunks
+            .iter()
+            .flat_map(change_blocks)
+            .map(|(_, new)| new)
+            .collect();
+        for hunk in &file.hunks {
+            if change_blocks(hunk)
+                .iter()
+                .any(|(old, _)| target_changes.iter().any(|t| overlaps(old, t)))
+            {
+                overlapping += 1;
+            }
+        }
+    }
+    overlapping
+}
+
+/// The old and new line ranges of each run of changed lines in `hunk`.
+fn change_blocks(hunk: &Hunk) -> Vec<(Range<usize>, Range<usize>)> {
+    let mut blocks = Vec::new();
+    let (mut old, mut new) = (hunk.old_start, hunk.new_start);
+    let mut block_start = None;
+    for (kind, _) in &hunk.lines {
+        match kind {
+            DiffLineType::Context => {
+                if let Some((old_start, new_start)) = block_start.take() {
+                    blocks.push((changed_range(old_start, old), changed_range(new_start, new)));
+                }
+                old += 1;
+                new += 1;
+            }
+            DiffLineType::Removed => {
+                block_start.get_or_insert((old, new));
+                old += 1;
+            }
+            DiffLineType::Added => {
+                block_start.get_or_insert((old, new));
+                new += 1;
+            }
+        }
+    }
+    if let Some((old_start, new_start)) = block_start {
+        blocks.push((changed_range(old_start, old), changed_range(new_start, new)));
+    }
+    blocks
+}
+
+/// Empty ranges, from pure insertions or deletions, are widened to the line
+/// they sit at.
+fn changed_range(start: usize, end: usize) -> Range<usize> {
+    start..end.max(start + 1)
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
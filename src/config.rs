@@ -1,24 +1,97 @@
-use anyhow::{bail, Result};
-use jj_lib::config::StackedConfig;
+use anyhow::{bail, Context, Result};
+use jj_lib::config::{ConfigGetError, ConfigGetResultExt, StackedConfig};
+use jj_lib::trailer::Trailer;
+use regex::Regex;
+use serde::de::DeserializeOwned;
 use walkdir::WalkDir;
 
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 
 use etcetera::BaseStrategy;
 use jj_lib::config::{ConfigLayer, ConfigSource, ConfigValue};
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+const DEFAULT_TOKEN_BUDGET: usize = 32000;
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 8192;
+const DEFAULT_BOOKMARK_PATTERN: &str = "{type}/{slug}";
+const DEFAULT_CUSTOM_SUBJECT_LEN: usize = 72;
+const DEFAULT_PROMPT_FILE: &str = ".jj-ai/prompt.md";
+
+#[derive(Debug, Clone, Default)]
 pub enum CommitStandard {
     #[default]
     Generic,
     Conventional,
     Gitmoji,
+    Custom(Arc<CustomStandard>),
+}
+
+/// A commit standard defined in an `ai.standards.<name>` table.
+#[derive(Debug, Clone)]
+pub struct CustomStandard {
+    pub name: String,
+    pub instructions: String,
+    /// Descriptions that follow the standard, shown to the model.
+    pub examples: Vec<String>,
+    /// Patterns every description must match, by rule name.
+    pub patterns: Vec<(String, Regex)>,
+    pub max_subject_length: usize,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CustomStandardTable {
+    instructions: String,
+    #[serde(default)]
+    examples: Vec<String>,
+    #[serde(default)]
+    patterns: BTreeMap<String, String>,
+    max_subject_length: Option<usize>,
+}
+
+impl CustomStandard {
+    fn from_table(name: &str, table: CustomStandardTable) -> Result<Self> {
+        let patterns = table
+            .patterns
+            .into_iter()
+            .map(|(rule, pattern)| {
+                let regex = Regex::new(&pattern).with_context(|| {
+                    format!("invalid pattern for ai.standards.{name}.patterns.{rule}")
+                })?;
+                Ok((rule, regex))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            name: name.to_string(),
+            instructions: table.instructions,
+            examples: table.examples,
+            patterns,
+            max_subject_length: table
+                .max_subject_length
+                .unwrap_or(DEFAULT_CUSTOM_SUBJECT_LEN),
+        })
+    }
 }
 
 impl CommitStandard {
-    pub fn prompt_instructions(&self) -> &'static str {
-        match self {
+    /// Looks `name` up in the `ai.standards` tables, then among the built-in
+    /// standards.
+    fn from_config(config: &StackedConfig, name: &str) -> Result<Self> {
+        match config.get::<CustomStandardTable>(["ai", "standards", name]) {
+            Ok(table) => Ok(CommitStandard::Custom(Arc::new(CustomStandard::from_table(
+                name, table,
+            )?))),
+            Err(ConfigGetError::NotFound { .. }) => name.parse(),
+            Err(err) => Err(err).with_context(|| format!("invalid commit standard ai.standards.{name}")),
+        }
+    }
+
+    pub fn prompt_instructions(&self) -> String {
+        let builtin = match self {
             CommitStandard::Generic => {
                 "Follow the 50/72 rule for commit messages:\n\
                  - Subject line: max 50 characters, capitalized, no trailing period\n\
@@ -47,7 +120,19 @@ impl CommitStandard {
                  🔒 for security fixes\n\
                  Example: ✨ add OAuth2 login support"
             }
-        }
+            CommitStandard::Custom(custom) => {
+                let mut instructions = custom.instructions.trim_end().to_string();
+                if !custom.examples.is_empty() {
+                    instructions.push_str("\n\nExamples of messages following this format:");
+                    for example in &custom.examples {
+                        instructions.push_str(&format!("\n---\n{}", example.trim()));
+                    }
+                    instructions.push_str("\n---");
+                }
+                return instructions;
+            }
+        };
+        builtin.to_string()
     }
 }
 
@@ -59,21 +144,160 @@ impl FromStr for CommitStandard {
             "generic" => Ok(CommitStandard::Generic),
             "conventional" => Ok(CommitStandard::Conventional),
             "gitmoji" => Ok(CommitStandard::Gitmoji),
-            other => bail!("invalid commit standard '{other}', expected one of: generic, conventional, gitmoji"),
+            other => bail!("invalid commit standard '{other}', expected one of: generic, conventional, gitmoji, or a table in ai.standards"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProviderKind {
+    #[default]
+    OpenRouter,
+    Anthropic,
+    OpenAi,
+    Mock,
+}
+
+impl ProviderKind {
+    /// Environment variable that holds the API key for this provider.
+    pub fn api_key_env(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::OpenRouter => Some("OPENROUTER_API_KEY"),
+            ProviderKind::Anthropic => Some("ANTHROPIC_API_KEY"),
+            ProviderKind::OpenAi => Some("OPENAI_API_KEY"),
+            ProviderKind::Mock => None,
+        }
+    }
+}
+
+impl FromStr for ProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "openrouter" => Ok(ProviderKind::OpenRouter),
+            "anthropic" => Ok(ProviderKind::Anthropic),
+            "openai" => Ok(ProviderKind::OpenAi),
+            "mock" => Ok(ProviderKind::Mock),
+            other => bail!("invalid provider '{other}', expected one of: openrouter, anthropic, openai, mock"),
+        }
+    }
+}
+
+/// What to do with diffs that exceed the model's token budget.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OversizeStrategy {
+    /// Cut the largest files short, keeping per-file stats.
+    #[default]
+    Truncate,
+    /// Summarize the diff in chunks and write the message from the summaries.
+    Chunk,
+}
+
+impl FromStr for OversizeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "truncate" => Ok(OversizeStrategy::Truncate),
+            "chunk" => Ok(OversizeStrategy::Chunk),
+            other => bail!("invalid oversize strategy '{other}', expected one of: truncate, chunk"),
+        }
+    }
+}
+
+/// Where `describe` puts ticket ids taken from bookmarks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TicketPlacement {
+    /// As the Conventional Commits scope: `feat(PROJ-1): ...`.
+    Scope,
+    /// Before the subject: `PROJ-1: ...`.
+    Prefix,
+    /// In a `Refs: PROJ-1` trailer.
+    Trailer,
+}
+
+impl FromStr for TicketPlacement {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "scope" => Ok(TicketPlacement::Scope),
+            "prefix" => Ok(TicketPlacement::Prefix),
+            "trailer" => Ok(TicketPlacement::Trailer),
+            other => bail!("invalid ticket placement '{other}', expected one of: scope, prefix, trailer"),
+        }
+    }
+}
+
+/// How `describe` treats commits with unresolved conflicts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Describe the commit and print a warning.
+    #[default]
+    Warn,
+    /// Leave the commit's description untouched.
+    Skip,
+    /// Ask the model to mention the conflicts in the message.
+    Mention,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "warn" => Ok(ConflictPolicy::Warn),
+            "skip" => Ok(ConflictPolicy::Skip),
+            "mention" => Ok(ConflictPolicy::Mention),
+            other => bail!("invalid conflict policy '{other}', expected one of: warn, skip, mention"),
         }
     }
 }
 
 pub struct JjaiConfig {
-    api_key: String,
+    provider: ProviderKind,
+    base_url: Option<String>,
+    api_key: Option<String>,
     model: String,
     ignore: Vec<String>,
     standard: CommitStandard,
+    token_budget: usize,
+    oversize: OversizeStrategy,
+    conflicts: ConflictPolicy,
+    concurrency: usize,
+    max_retries: u32,
+    max_output_tokens: u32,
+    stack_context: bool,
+    bookmark_pattern: String,
+    ticket_pattern: Option<Regex>,
+    ticket_placement: TicketPlacement,
+    sign_off: bool,
+    model_trailer: bool,
+    extra_trailers: Vec<Trailer>,
+    prompt_file: PathBuf,
+    history_examples: usize,
+    project_prompt: Option<String>,
+    examples: Vec<String>,
+    mock_response: Option<String>,
+    mock_responses_file: Option<PathBuf>,
 }
 
 impl JjaiConfig {
-    pub fn api_key(&self) -> &str {
-        &self.api_key
+    pub fn provider(&self) -> ProviderKind {
+        self.provider
+    }
+
+    /// Overrides the provider's default API endpoint, e.g. to point at a
+    /// local OpenAI-compatible server.
+    pub fn base_url(&self) -> Option<&str> {
+        self.base_url.as_deref()
+    }
+
+    /// API key for the provider. Local endpoints usually don't need one, so
+    /// a missing key is only an error for providers that require it.
+    pub fn api_key(&self) -> Option<&str> {
+        self.api_key.as_deref()
     }
 
     pub fn model(&self) -> &str {
@@ -84,8 +308,114 @@ impl JjaiConfig {
         &self.ignore
     }
 
-    pub fn standard(&self) -> CommitStandard {
-        self.standard
+    pub fn standard(&self) -> &CommitStandard {
+        &self.standard
+    }
+
+    /// Maximum number of diff tokens to send to the model in one request.
+    pub fn token_budget(&self) -> usize {
+        self.token_budget
+    }
+
+    pub fn oversize(&self) -> OversizeStrategy {
+        self.oversize
+    }
+
+    pub fn conflicts(&self) -> ConflictPolicy {
+        self.conflicts
+    }
+
+    /// Maximum number of requests in flight to the provider at once.
+    pub fn concurrency(&self) -> usize {
+        self.concurrency
+    }
+
+    /// How often a rate-limited or failed request is retried.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    /// Whether commits wait for their parents in the revset to be described,
+    /// to see the new descriptions. This serializes linear stacks.
+    pub fn stack_context(&self) -> bool {
+        self.stack_context
+    }
+
+    /// Cap on the tokens the model may generate in one response, for
+    /// providers that require one.
+    pub fn max_output_tokens(&self) -> u32 {
+        self.max_output_tokens
+    }
+
+    /// Template for generated bookmark names, with `{user}`, `{type}`,
+    /// `{slug}` and `{change}` placeholders.
+    pub fn bookmark_pattern(&self) -> &str {
+        &self.bookmark_pattern
+    }
+
+    /// Pattern of ticket ids to find in bookmark names, if tickets are
+    /// required in descriptions.
+    pub fn ticket_pattern(&self) -> Option<&Regex> {
+        self.ticket_pattern.as_ref()
+    }
+
+    pub fn ticket_placement(&self) -> TicketPlacement {
+        self.ticket_placement
+    }
+
+    /// Whether `describe` adds a `Signed-off-by` trailer for the user.
+    pub fn sign_off(&self) -> bool {
+        self.sign_off
+    }
+
+    /// Whether `describe` adds an `AI-Model` trailer naming the model.
+    pub fn model_trailer(&self) -> bool {
+        self.model_trailer
+    }
+
+    /// Trailers `describe` adds to every description, such as
+    /// `Co-authored-by`.
+    pub fn extra_trailers(&self) -> &[Trailer] {
+        &self.extra_trailers
+    }
+
+    /// Checked-in prompt file with project guidelines, relative to the
+    /// workspace root.
+    pub fn prompt_file(&self) -> &Path {
+        &self.prompt_file
+    }
+
+    /// How many recent descriptions from `trunk()` to show the model as
+    /// examples of the project's style.
+    pub fn history_examples(&self) -> usize {
+        self.history_examples
+    }
+
+    /// Contents of the prompt file, once loaded from the workspace.
+    pub fn project_prompt(&self) -> Option<&str> {
+        self.project_prompt.as_deref()
+    }
+
+    /// Descriptions sampled from the project's history.
+    pub fn examples(&self) -> &[String] {
+        &self.examples
+    }
+
+    /// Sets the guidelines and examples found in the workspace, which the
+    /// config alone doesn't know about.
+    pub(crate) fn set_project_style(&mut self, prompt: Option<String>, examples: Vec<String>) {
+        self.project_prompt = prompt;
+        self.examples = examples;
+    }
+
+    /// Fixed reply of the `mock` provider.
+    pub fn mock_response(&self) -> Option<&str> {
+        self.mock_response.as_deref()
+    }
+
+    /// JSON file of `mock` provider replies keyed by diff hash.
+    pub fn mock_responses_file(&self) -> Option<&Path> {
+        self.mock_responses_file.as_deref()
     }
 }
 
@@ -93,28 +423,104 @@ impl TryFrom<&StackedConfig> for JjaiConfig {
     type Error = anyhow::Error;
 
     fn try_from(value: &StackedConfig) -> Result<Self> {
-        let standard_str: String = value
-            .get("ai.standard")
-            .unwrap_or_else(|_| "conventional".to_string());
-        let standard = standard_str.parse::<CommitStandard>()?;
+        let standard_str: String = get_or(value, "ai.standard", "conventional".to_string())?;
+        let standard = CommitStandard::from_config(value, &standard_str)?;
+
+        let provider_str: String = get_or(value, "ai.provider", "openrouter".to_string())?;
+        let provider = provider_str.parse::<ProviderKind>()?;
+
+        let model: String = value.get("ai.model")?;
+
+        // A per-model budget from `ai.token-budgets` wins over the default.
+        let token_budgets: HashMap<String, usize> =
+            get_or(value, "ai.token-budgets", HashMap::new())?;
+        let token_budget = match token_budgets.get(&model) {
+            Some(budget) => *budget,
+            None => get_or(value, "ai.token-budget", DEFAULT_TOKEN_BUDGET)?,
+        };
+
+        let oversize_str: String = get_or(value, "ai.oversize", "truncate".to_string())?;
+        let oversize = oversize_str.parse::<OversizeStrategy>()?;
+
+        let conflicts_str: String = get_or(value, "ai.conflicts", "warn".to_string())?;
+        let conflicts = conflicts_str.parse::<ConflictPolicy>()?;
+
+        let ticket_pattern = match get_optional::<String>(value, "ai.ticket-pattern")? {
+            Some(pattern) => Some(Regex::new(&pattern).context("invalid ai.ticket-pattern")?),
+            None => None,
+        };
+        // Scopes are a Conventional Commits notion; other standards default
+        // to a trailer.
+        let ticket_placement = match get_optional::<String>(value, "ai.ticket-placement")? {
+            Some(placement) => placement.parse::<TicketPlacement>()?,
+            None if matches!(standard, CommitStandard::Conventional) => TicketPlacement::Scope,
+            None => TicketPlacement::Trailer,
+        };
+
+        let extra_trailers = get_or::<Vec<String>>(value, "ai.trailers.add", Vec::new())?
+            .iter()
+            .map(|line| crate::trailer::parse_trailer(line))
+            .collect::<Result<_>>()
+            .context("invalid ai.trailers.add")?;
 
         Ok(Self {
-            api_key: value
-                .get("ai.api-key")
-                .map_err(|_| anyhow::anyhow!("missing ai.api-key in jj config or OPENROUTER_API_KEY env var"))?,
-            model: value.get("ai.model").unwrap(),
-            ignore: value.get("ai.ignore").unwrap_or_default(),
+            provider,
+            base_url: get_optional(value, "ai.base-url")?,
+            api_key: get_optional(value, "ai.api-key")?,
+            model,
+            ignore: get_or(value, "ai.ignore", Vec::new())?,
             standard,
+            token_budget,
+            oversize,
+            conflicts,
+            concurrency: get_or::<usize>(value, "ai.concurrency", DEFAULT_CONCURRENCY)?.max(1),
+            max_retries: get_or(value, "ai.max-retries", DEFAULT_MAX_RETRIES)?,
+            max_output_tokens: get_or(value, "ai.max-output-tokens", DEFAULT_MAX_OUTPUT_TOKENS)?,
+            stack_context: get_or(value, "ai.stack-context", true)?,
+            bookmark_pattern: get_or(
+                value,
+                "ai.bookmark-pattern",
+                DEFAULT_BOOKMARK_PATTERN.to_string(),
+            )?,
+            ticket_pattern,
+            ticket_placement,
+            sign_off: get_or(value, "ai.trailers.signed-off-by", false)?,
+            model_trailer: get_or(value, "ai.trailers.model", false)?,
+            extra_trailers,
+            prompt_file: get_or(value, "ai.prompt-file", PathBuf::from(DEFAULT_PROMPT_FILE))?,
+            history_examples: get_or(value, "ai.history-examples", 0)?,
+            project_prompt: None,
+            examples: Vec::new(),
+            mock_response: get_optional(value, "ai.mock.response")?,
+            mock_responses_file: get_optional(value, "ai.mock.responses-file")?,
         })
     }
 }
 
-pub fn load_stacked_config(workspace_root: &PathBuf) -> Result<StackedConfig> {
+/// Reads `name` if it is set. Values of the wrong type are errors rather
+/// than silently ignored.
+fn get_optional<T: DeserializeOwned>(
+    config: &StackedConfig,
+    name: &'static str,
+) -> Result<Option<T>> {
+    Ok(config.get(name).optional()?)
+}
+
+/// Reads `name`, falling back to `default` only when it is not set.
+fn get_or<T: DeserializeOwned>(
+    config: &StackedConfig,
+    name: &'static str,
+    default: T,
+) -> Result<T> {
+    Ok(get_optional(config, name)?.unwrap_or(default))
+}
+
+pub fn load_stacked_config(workspace_root: &Path) -> Result<StackedConfig> {
     let mut config = StackedConfig::with_defaults();
     config.add_layer(env_base_layer());
     config.extend_layers(user_layers());
     config.extend_layers(workspace_layers(workspace_root));
-    config.add_layer(env_overrides_layer());
+    config.add_layer(env_overrides_layer(&config));
     Ok(config)
 }
 
@@ -124,15 +530,42 @@ fn env_base_layer() -> ConfigLayer {
     let ignore_array: ConfigValue = ["*.lock"].into_iter().collect();
     let _ = layer.set_value("ai.ignore", ignore_array);
     let _ = layer.set_value("ai.standard", "generic");
+    let _ = layer.set_value("ai.provider", "openrouter");
+    let _ = layer.set_value("ai.token-budget", DEFAULT_TOKEN_BUDGET as i64);
+    let _ = layer.set_value("ai.oversize", "truncate");
+    let _ = layer.set_value("ai.conflicts", "warn");
+    let _ = layer.set_value("ai.concurrency", DEFAULT_CONCURRENCY as i64);
+    let _ = layer.set_value("ai.max-retries", DEFAULT_MAX_RETRIES as i64);
+    let _ = layer.set_value("ai.max-output-tokens", DEFAULT_MAX_OUTPUT_TOKENS as i64);
+    let _ = layer.set_value("ai.stack-context", true);
+    let _ = layer.set_value("ai.bookmark-pattern", DEFAULT_BOOKMARK_PATTERN);
+    let _ = layer.set_value("ai.prompt-file", DEFAULT_PROMPT_FILE);
+    let _ = layer.set_value("ai.history-examples", 0);
     layer
 }
 
-fn env_overrides_layer() -> ConfigLayer {
+/// Settings from the environment, which win over the config files. The API
+/// key variable depends on the provider chosen in `config` or the
+/// environment.
+fn env_overrides_layer(config: &StackedConfig) -> ConfigLayer {
     let mut layer = ConfigLayer::empty(ConfigSource::EnvOverrides);
 
-    if let Ok(value) = std::env::var("OPENROUTER_API_KEY") {
+    let provider = std::env::var("JJ_AI_PROVIDER")
+        .ok()
+        .or_else(|| config.get::<String>("ai.provider").ok());
+    let key_env = provider
+        .and_then(|provider| provider.parse::<ProviderKind>().ok())
+        .and_then(|provider| provider.api_key_env());
+    if let Some(Ok(value)) = key_env.map(std::env::var) {
         let _ = layer.set_value("ai.api-key", value);
     }
+
+    if let Ok(value) = std::env::var("JJ_AI_PROVIDER") {
+        let _ = layer.set_value("ai.provider", value);
+    }
+    if let Ok(value) = std::env::var("JJ_AI_BASE_URL") {
+        let _ = layer.set_value("ai.base-url", value);
+    }
     if let Ok(value) = std::env::var("JJ_AI_MODEL") {
         let _ = layer.set_value("ai.model", value);
     }
@@ -140,7 +573,7 @@ fn env_overrides_layer() -> ConfigLayer {
     layer
 }
 
-fn workspace_layers(workspace_root: &PathBuf) -> Vec<ConfigLayer> {
+fn workspace_layers(workspace_root: &Path) -> Vec<ConfigLayer> {
     let mut layers = Vec::new();
 
     let repo_config = workspace_root.join(".jj/repo/config.toml");
@@ -0,0 +1,66 @@
+/// Commit types of the Conventional Commits standard and the changelog
+/// section each one is listed under.
+pub const TYPES: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("docs", "Documentation"),
+    ("style", "Styles"),
+    ("test", "Tests"),
+    ("build", "Build"),
+    ("ci", "CI"),
+    ("chore", "Chores"),
+    ("revert", "Reverts"),
+];
+
+/// The parts of a `<type>(<scope>)!: <subject>` header line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header<'a> {
+    pub kind: &'a str,
+    pub scope: Option<&'a str>,
+    pub breaking: bool,
+    pub subject: &'a str,
+}
+
+/// Parses a Conventional Commits header. The type is not checked against
+/// [`TYPES`], so callers can report unknown types separately.
+pub fn parse_header(line: &str) -> Option<Header<'_>> {
+    let (prefix, subject) = line.split_once(": ")?;
+    let (prefix, breaking) = match prefix.strip_suffix('!') {
+        Some(prefix) => (prefix, true),
+        None => (prefix, false),
+    };
+    let (kind, scope) = match prefix.split_once('(') {
+        Some((kind, scope)) => {
+            let scope = scope.strip_suffix(')')?;
+            if scope.is_empty() || scope.contains(['(', ')']) {
+                return None;
+            }
+            (kind, Some(scope))
+        }
+        None => (prefix, None),
+    };
+
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_lowercase()) {
+        return None;
+    }
+    if subject.trim().is_empty() {
+        return None;
+    }
+
+    Some(Header {
+        kind,
+        scope,
+        breaking,
+        subject,
+    })
+}
+
+/// The changelog section for a commit type, if it is a known one.
+pub fn section_for(kind: &str) -> Option<&'static str> {
+    TYPES
+        .iter()
+        .find(|(known, _)| *known == kind)
+        .map(|(_, section)| *section)
+}
@@ -2,24 +2,168 @@ use anyhow::{Context, Result};
 use bstr::{BStr, ByteSlice};
 use futures::StreamExt;
 use glob::Pattern;
-use jj_lib::backend::TreeValue;
 use jj_lib::commit::Commit;
+use jj_lib::conflict_labels::ConflictLabels;
+use jj_lib::conflicts::{
+    materialize_merge_result_to_bytes, materialize_tree_value, ConflictMarkerStyle,
+    ConflictMaterializeOptions, MaterializedTreeValue,
+};
+use jj_lib::copies::{CopyOperation, CopyRecords};
 use jj_lib::diff_presentation::unified::{unified_diff_hunks, DiffLineType};
 use jj_lib::diff_presentation::LineCompareMode;
-use jj_lib::matchers::EverythingMatcher;
+use jj_lib::matchers::{EverythingMatcher, Matcher};
 use jj_lib::merge::{Diff, MergedTreeValue};
+use jj_lib::object_id::ObjectId;
 use jj_lib::repo::Repo;
-use std::fmt::Write;
+use jj_lib::repo_path::RepoPath;
+use jj_lib::rewrite::merge_commit_trees;
+use jj_lib::settings::UserSettings;
+use jj_lib::tree_merge::MergeOptions;
+use std::fmt;
 use std::sync::Arc;
-use tokio::io::AsyncReadExt;
 
 const CONTEXT_LINES: usize = 3;
 
+/// The diff of a commit against its parent, kept per file so it can be
+/// budgeted, filtered or regrouped before being rendered for the model.
+#[derive(Debug, Clone, Default)]
+pub struct CommitPatch {
+    pub files: Vec<FilePatch>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    pub path: String,
+    /// The path before the change; differs from `path` for renames and copies.
+    pub source: String,
+    /// Git-style extended header lines, starting with `diff --git`.
+    pub header: Vec<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    pub old_start: usize,
+    pub old_len: usize,
+    pub new_start: usize,
+    pub new_len: usize,
+    /// Lines including their trailing newline, if any.
+    pub lines: Vec<(DiffLineType, String)>,
+}
+
+impl CommitPatch {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl FilePatch {
+    pub fn added(&self) -> usize {
+        self.count_lines(DiffLineType::Added)
+    }
+
+    pub fn removed(&self) -> usize {
+        self.count_lines(DiffLineType::Removed)
+    }
+
+    fn count_lines(&self, kind: DiffLineType) -> usize {
+        self.hunks
+            .iter()
+            .flat_map(|hunk| &hunk.lines)
+            .filter(|(line_type, _)| *line_type == kind)
+            .count()
+    }
+
+    /// A `path | +added -removed` summary line.
+    pub fn stat(&self) -> String {
+        format!("{} | +{} -{}", self.path, self.added(), self.removed())
+    }
+}
+
+impl fmt::Display for CommitPatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.files.iter().try_for_each(|file| write!(f, "{file}"))
+    }
+}
+
+impl fmt::Display for FilePatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.header {
+            writeln!(f, "{line}")?;
+        }
+        self.hunks.iter().try_for_each(|hunk| write!(f, "{hunk}"))
+    }
+}
+
+impl Hunk {
+    pub fn header(&self) -> String {
+        format!(
+            "@@ -{},{} +{},{} @@\n",
+            self.old_start, self.old_len, self.new_start, self.new_len
+        )
+    }
+}
+
+pub fn render_line(line_type: DiffLineType, content: &str) -> String {
+    let prefix = match line_type {
+        DiffLineType::Context => " ",
+        DiffLineType::Removed => "-",
+        DiffLineType::Added => "+",
+    };
+    if content.ends_with('\n') {
+        format!("{prefix}{content}")
+    } else {
+        format!("{prefix}{content}\n\\ No newline at end of file\n")
+    }
+}
+
+impl fmt::Display for Hunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.header())?;
+        for (line_type, content) in &self.lines {
+            write!(f, "{}", render_line(*line_type, content))?;
+        }
+        Ok(())
+    }
+}
+
 pub async fn render_commit_patch<R: Repo>(
     repo: &R,
     commit: &Commit,
     ignore_patterns: &[String],
 ) -> Result<String> {
+    Ok(commit_patch(repo, commit, ignore_patterns).await?.to_string())
+}
+
+/// Which files to diff and how many unchanged lines to show around changes.
+pub struct PatchOptions<'a> {
+    pub matcher: &'a dyn Matcher,
+    pub context_lines: usize,
+}
+
+impl Default for PatchOptions<'_> {
+    fn default() -> Self {
+        Self {
+            matcher: &EverythingMatcher,
+            context_lines: CONTEXT_LINES,
+        }
+    }
+}
+
+pub async fn commit_patch<R: Repo>(
+    repo: &R,
+    commit: &Commit,
+    ignore_patterns: &[String],
+) -> Result<CommitPatch> {
+    commit_patch_with(repo, commit, ignore_patterns, &PatchOptions::default()).await
+}
+
+pub async fn commit_patch_with<R: Repo>(
+    repo: &R,
+    commit: &Commit,
+    ignore_patterns: &[String],
+    options: &PatchOptions<'_>,
+) -> Result<CommitPatch> {
     let patterns: Vec<Pattern> = ignore_patterns
         .iter()
         .filter_map(|p| Pattern::new(p).ok())
@@ -30,97 +174,235 @@ pub async fn render_commit_patch<R: Repo>(
         .collect::<Result<Vec<_>, _>>()
         .context("failed to load parents")?;
 
+    // Merge commits are diffed against the automatic merge of their parents,
+    // like `jj diff` does, so only changes made in the merge itself show up.
     let parent_tree = if parents.is_empty() {
         repo.store().empty_merged_tree()
     } else {
-        parents[0].tree()
+        merge_commit_trees(repo, &parents)
+            .await
+            .context("failed to merge parent trees")?
     };
 
     let commit_tree = commit.tree();
 
-    let mut output = String::new();
+    // Copy records let moved files show up as renames with only their
+    // content delta instead of a full deletion plus a full addition.
+    let mut copy_records = CopyRecords::default();
+    if let [parent] = parents.as_slice() {
+        let records: Vec<_> = repo
+            .store()
+            .get_copy_records(None, parent.id(), commit.id())
+            .context("failed to get copy records")?
+            .collect()
+            .await;
+        copy_records
+            .add_records(records)
+            .context("failed to read copy records")?;
+    }
+
+    let materialize_options = materialize_options(repo.base_repo().settings())?;
+
+    let mut patch = CommitPatch::default();
 
-    let diff_stream = parent_tree.diff_stream(&commit_tree, &EverythingMatcher);
+    let diff_stream =
+        parent_tree.diff_stream_with_copies(&commit_tree, options.matcher, &copy_records);
     let entries: Vec<_> = diff_stream.collect().await;
 
     for entry in entries {
-        let path_str = entry.path.as_internal_file_string();
+        let path_str = entry.path.target().as_internal_file_string();
         if patterns.iter().any(|p| p.matches(path_str)) {
             continue;
         }
-        let path = entry.path;
+        let path = path_str.to_string();
+        let source = entry.path.source().as_internal_file_string().to_string();
         let diff_values = entry.values.context("failed to get diff values")?;
 
-        writeln!(
-            output,
-            "diff --git a/{} b/{}",
-            path.as_internal_file_string(),
-            path.as_internal_file_string()
+        let before = read_side(
+            repo.store(),
+            entry.path.source(),
+            &diff_values.before,
+            parent_tree.labels(),
+            &materialize_options,
         )
-        .context("failed to write diff header")?;
-
-        let before_content = get_content(repo.store(), &diff_values.before).await?;
-        let after_content = get_content(repo.store(), &diff_values.after).await?;
-
-        let before_bstr: &BStr = before_content.as_bytes().as_bstr();
-        let after_bstr: &BStr = after_content.as_bytes().as_bstr();
-        let contents = Diff::new(before_bstr, after_bstr);
-
-        let hunks = unified_diff_hunks(contents, CONTEXT_LINES, LineCompareMode::Exact);
-
-        for hunk in hunks {
-            let left_start = hunk.left_line_range.start + 1;
-            let left_len = hunk.left_line_range.len();
-            let right_start = hunk.right_line_range.start + 1;
-            let right_len = hunk.right_line_range.len();
-
-            writeln!(
-                output,
-                "@@ -{},{} +{},{} @@",
-                left_start, left_len, right_start, right_len
-            )
-            .context("failed to write hunk header")?;
-
-            for (line_type, tokens) in &hunk.lines {
-                let prefix = match line_type {
-                    DiffLineType::Context => " ",
-                    DiffLineType::Removed => "-",
-                    DiffLineType::Added => "+",
-                };
-
-                let line_content: String = tokens
-                    .iter()
-                    .map(|(_, bytes)| bytes.to_str_lossy())
-                    .collect();
+        .await?;
+        let after = read_side(
+            repo.store(),
+            entry.path.target(),
+            &diff_values.after,
+            commit_tree.labels(),
+            &materialize_options,
+        )
+        .await?;
 
-                write!(output, "{}{}", prefix, line_content)
-                    .context("failed to write diff line")?;
+        let mut header = vec![format!("diff --git a/{source} b/{path}")];
+        match (before.mode, after.mode) {
+            (None, Some(mode)) => header.push(format!("new file mode {mode}")),
+            (Some(mode), None) => header.push(format!("deleted file mode {mode}")),
+            (Some(old), Some(new)) if old != new => {
+                header.push(format!("old mode {old}"));
+                header.push(format!("new mode {new}"));
             }
+            _ => {}
         }
+        match entry.path.copy_operation() {
+            Some(CopyOperation::Rename) => {
+                header.push(format!("rename from {source}"));
+                header.push(format!("rename to {path}"));
+            }
+            Some(CopyOperation::Copy) => {
+                header.push(format!("copy from {source}"));
+                header.push(format!("copy to {path}"));
+            }
+            None => {}
+        }
+
+        let hunks = if is_binary(&before.content) || is_binary(&after.content) {
+            if before.content != after.content {
+                let old_name = match before.mode {
+                    Some(_) => format!("a/{source}"),
+                    None => "/dev/null".to_string(),
+                };
+                let new_name = match after.mode {
+                    Some(_) => format!("b/{path}"),
+                    None => "/dev/null".to_string(),
+                };
+                header.push(format!("Binary files {old_name} and {new_name} differ"));
+            }
+            Vec::new()
+        } else {
+            text_hunks(&before.content, &after.content, options.context_lines)
+        };
+
+        patch.files.push(FilePatch {
+            path,
+            source,
+            header,
+            hunks,
+        });
     }
 
-    Ok(output)
+    Ok(patch)
 }
 
-async fn get_content(store: &Arc<jj_lib::store::Store>, value: &MergedTreeValue) -> Result<String> {
-    if value.is_absent() {
-        return Ok(String::new());
+/// How conflicts are rendered with markers, following the user's
+/// `ui.conflict-marker-style`.
+pub fn materialize_options(settings: &UserSettings) -> Result<ConflictMaterializeOptions> {
+    Ok(ConflictMaterializeOptions {
+        marker_style: settings
+            .get("ui.conflict-marker-style")
+            .unwrap_or(ConflictMarkerStyle::Diff),
+        marker_len: None,
+        merge: MergeOptions::from_settings(settings).context("failed to load merge options")?,
+    })
+}
+
+/// A diff of two versions of the text file at `path`.
+pub fn text_patch(path: &str, before: &[u8], after: &[u8]) -> FilePatch {
+    FilePatch {
+        path: path.to_string(),
+        source: path.to_string(),
+        header: vec![format!("diff --git a/{path} b/{path}")],
+        hunks: text_hunks(before, after, CONTEXT_LINES),
     }
+}
 
-    let resolved = value.as_resolved();
-    if let Some(Some(TreeValue::File { id, .. })) = resolved {
-        let mut reader = store
-            .read_file(&jj_lib::repo_path::RepoPath::root(), id)
-            .await
-            .context("failed to read file content")?;
+fn text_hunks(before: &[u8], after: &[u8], context_lines: usize) -> Vec<Hunk> {
+    let before_bstr: &BStr = before.as_bstr();
+    let after_bstr: &BStr = after.as_bstr();
+    let contents = Diff::new(before_bstr, after_bstr);
 
-        let mut buf = Vec::new();
-        AsyncReadExt::read_to_end(&mut reader, &mut buf)
-            .await
-            .context("failed to read file bytes")?;
+    unified_diff_hunks(contents, context_lines, LineCompareMode::Exact)
+        .into_iter()
+        .map(|hunk| Hunk {
+            old_start: hunk.left_line_range.start + 1,
+            old_len: hunk.left_line_range.len(),
+            new_start: hunk.right_line_range.start + 1,
+            new_len: hunk.right_line_range.len(),
+            lines: hunk
+                .lines
+                .iter()
+                .map(|(line_type, tokens)| {
+                    let content: String = tokens
+                        .iter()
+                        .map(|(_, bytes)| bytes.to_str_lossy())
+                        .collect();
+                    (*line_type, content)
+                })
+                .collect(),
+        })
+        .collect()
+}
 
-        Ok(String::from_utf8_lossy(&buf).to_string())
-    } else {
-        Ok(String::new())
+/// One side of a file diff: its git file mode (`None` if absent) and the
+/// content to diff. Symlinks and submodules are diffed as their target.
+struct Side {
+    mode: Option<&'static str>,
+    content: Vec<u8>,
+}
+
+impl Side {
+    fn absent() -> Self {
+        Self {
+            mode: None,
+            content: Vec::new(),
+        }
+    }
+}
+
+/// Git treats content with a NUL byte in the first 8000 bytes as binary.
+fn is_binary(content: &[u8]) -> bool {
+    content.iter().take(8000).any(|&b| b == 0)
+}
+
+async fn read_side(
+    store: &Arc<jj_lib::store::Store>,
+    path: &RepoPath,
+    value: &MergedTreeValue,
+    labels: &ConflictLabels,
+    options: &ConflictMaterializeOptions,
+) -> Result<Side> {
+    let materialized = materialize_tree_value(store, path, value.clone(), labels)
+        .await
+        .context("failed to read file content")?;
+
+    match materialized {
+        MaterializedTreeValue::Absent | MaterializedTreeValue::Tree(_) => Ok(Side::absent()),
+        MaterializedTreeValue::AccessDenied(err) => Ok(Side {
+            mode: Some("100644"),
+            content: format!("(access denied: {err})\n").into_bytes(),
+        }),
+        MaterializedTreeValue::File(mut file) => {
+            let content = file
+                .read_all(path)
+                .await
+                .context("failed to read file bytes")?;
+            let mode = if file.executable { "100755" } else { "100644" };
+            Ok(Side {
+                mode: Some(mode),
+                content,
+            })
+        }
+        MaterializedTreeValue::Symlink { target, .. } => Ok(Side {
+            mode: Some("120000"),
+            content: target.into_bytes(),
+        }),
+        MaterializedTreeValue::GitSubmodule(id) => Ok(Side {
+            mode: Some("160000"),
+            content: format!("Subproject commit {}\n", id.hex()).into_bytes(),
+        }),
+        // Conflicted files are shown with conflict markers, as in the working copy.
+        MaterializedTreeValue::FileConflict(file) => {
+            let mode = if file.executable.unwrap_or(false) { "100755" } else { "100644" };
+            let content = materialize_merge_result_to_bytes(&file.contents, &file.labels, options);
+            Ok(Side {
+                mode: Some(mode),
+                content: content.into(),
+            })
+        }
+        MaterializedTreeValue::OtherConflict { .. } => Ok(Side {
+            mode: Some("100644"),
+            content: b"(unresolved conflict between non-file entries)\n".to_vec(),
+        }),
     }
 }
@@ -1,5 +1,10 @@
+pub mod budget;
 pub mod command;
 pub mod config;
+pub mod conventional;
 pub mod diff;
 pub mod editor;
+pub mod lint;
 pub mod llm;
+pub mod ticket;
+pub mod trailer;
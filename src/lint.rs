@@ -0,0 +1,162 @@
+use std::fmt;
+
+use crate::config::CommitStandard;
+use crate::conventional::{parse_header, TYPES};
+use crate::trailer::split_trailers;
+
+/// Longest subject line for the generic standard.
+const MAX_SUBJECT_LEN: usize = 50;
+/// Longest header line for Conventional Commits and gitmoji, whose prefixes
+/// take up part of the line.
+const MAX_PREFIXED_SUBJECT_LEN: usize = 72;
+const MAX_BODY_LINE_LEN: usize = 72;
+
+/// Emojis from https://gitmoji.dev, without variation selectors.
+const GITMOJIS: &[&str] = &[
+    "🎨", "⚡", "🔥", "🐛", "🚑", "✨", "📝", "🚀", "💄", "🎉", "✅", "🔒", "🔐", "🔖", "🚨",
+    "🚧", "💚", "⬇", "⬆", "📌", "👷", "📈", "♻", "➕", "➖", "🔧", "🔨", "🌐", "✏", "💩",
+    "⏪", "🔀", "📦", "👽", "🚚", "📄", "💥", "🍱", "♿", "💡", "🍻", "💬", "🗃", "🔊", "🔇",
+    "👥", "🚸", "🏗", "📱", "🤡", "🥚", "🙈", "📸", "⚗", "🔍", "🏷", "🌱", "🚩", "🥅", "💫",
+    "🗑", "🛂", "🩹", "🧐", "⚰", "🧪", "👔", "🩺", "🧱", "🧑‍💻", "💸", "🧵", "🦺", "✈",
+];
+
+/// A rule a description breaks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub rule: String,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(rule: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            rule: rule.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+/// Checks `description` against the deterministic rules of `standard`.
+pub fn lint_description(standard: &CommitStandard, description: &str) -> Vec<Violation> {
+    let description = description.trim_end();
+    let mut lines = description.lines();
+    let Some(subject) = lines.next().filter(|line| !line.trim().is_empty()) else {
+        return vec![Violation::new("empty", "description is empty")];
+    };
+
+    let mut violations = Vec::new();
+    let max_subject_len = match standard {
+        CommitStandard::Generic => MAX_SUBJECT_LEN,
+        CommitStandard::Conventional | CommitStandard::Gitmoji => MAX_PREFIXED_SUBJECT_LEN,
+        CommitStandard::Custom(custom) => custom.max_subject_length,
+    };
+    let subject_len = subject.chars().count();
+    if subject_len > max_subject_len {
+        violations.push(Violation::new(
+            "subject-length",
+            format!("subject is {subject_len} characters, over {max_subject_len}"),
+        ));
+    }
+    if subject.ends_with('.') {
+        violations.push(Violation::new("subject-period", "subject ends with a period"));
+    }
+
+    match standard {
+        CommitStandard::Generic => {
+            if subject.starts_with(|c: char| c.is_lowercase()) {
+                violations.push(Violation::new(
+                    "subject-case",
+                    "subject does not start with a capital letter",
+                ));
+            }
+        }
+        CommitStandard::Conventional => match parse_header(subject) {
+            None => violations.push(Violation::new(
+                "conventional-header",
+                "subject is not of the form `<type>(<scope>): <description>`",
+            )),
+            Some(header) if !TYPES.iter().any(|(kind, _)| *kind == header.kind) => {
+                let types: Vec<&str> = TYPES.iter().map(|(kind, _)| *kind).collect();
+                violations.push(Violation::new(
+                    "conventional-type",
+                    format!(
+                        "unknown type '{}', expected one of: {}",
+                        header.kind,
+                        types.join(", ")
+                    ),
+                ));
+            }
+            Some(_) => {}
+        },
+        CommitStandard::Gitmoji => {
+            if !has_gitmoji_prefix(subject) {
+                violations.push(Violation::new(
+                    "gitmoji-prefix",
+                    "subject does not start with a gitmoji followed by a space",
+                ));
+            }
+        }
+        CommitStandard::Custom(custom) => {
+            for (rule, pattern) in &custom.patterns {
+                if !pattern.is_match(description) {
+                    violations.push(Violation::new(
+                        rule.as_str(),
+                        format!("description does not match `{pattern}`"),
+                    ));
+                }
+            }
+        }
+    }
+
+    if let Some(second) = lines.next() {
+        if !second.trim().is_empty() {
+            violations.push(Violation::new(
+                "body-separator",
+                "subject and body are not separated by a blank line",
+            ));
+        }
+    }
+    // Lines without spaces, such as URLs, can't be wrapped. Trailers are
+    // left alone, as they can't be wrapped either.
+    let (text, _) = split_trailers(description);
+    for (index, line) in text.lines().enumerate().skip(1) {
+        let len = line.chars().count();
+        if len > MAX_BODY_LINE_LEN && line.trim().contains(char::is_whitespace) {
+            violations.push(Violation::new(
+                "body-length",
+                format!(
+                    "line {} is {len} characters, over {MAX_BODY_LINE_LEN}",
+                    index + 1
+                ),
+            ));
+        }
+    }
+
+    violations
+}
+
+/// Whether `subject` starts with a gitmoji or its `:shortcode:`, then a space.
+pub(crate) fn has_gitmoji_prefix(subject: &str) -> bool {
+    let Some((prefix, rest)) = subject.split_once(' ') else {
+        return false;
+    };
+    if rest.trim().is_empty() {
+        return false;
+    }
+    if let Some(code) = prefix
+        .strip_prefix(':')
+        .and_then(|prefix| prefix.strip_suffix(':'))
+    {
+        return !code.is_empty()
+            && code
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    }
+    GITMOJIS.contains(&prefix.replace('\u{fe0f}', "").as_str())
+}
@@ -1,13 +1,167 @@
-use anyhow::Result;
+mod anthropic;
+mod mock;
+mod openai;
+mod openrouter;
+mod sse;
+mod throttle;
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use async_trait::async_trait;
 use orpheus::prelude::*;
+use serde::de::DeserializeOwned;
+
+use crate::budget::{chunk_patch, estimate_tokens, fit_patch, truncate_patch, truncate_text};
+use crate::config::{
+    CommitStandard, ConflictPolicy, JjaiConfig, OversizeStrategy, ProviderKind, TicketPlacement,
+};
+use crate::diff::CommitPatch;
+use crate::ticket::REFS_TRAILER;
+
+pub use anthropic::AnthropicProvider;
+pub use mock::{diff_hash, MockProvider};
+pub use openai::OpenAiProvider;
+pub use openrouter::OpenRouterProvider;
+pub use throttle::{ApiError, ThrottledProvider};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    System,
+    User,
+    Assistant,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+}
+
+impl ChatMessage {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::System,
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::User,
+            content: content.into(),
+        }
+    }
+
+    pub fn assistant(content: impl Into<String>) -> Self {
+        Self {
+            role: Role::Assistant,
+            content: content.into(),
+        }
+    }
+}
+
+/// A chat model backend.
+///
+/// Structured output schemas are described with orpheus' [`Format`], which
+/// every provider translates into its own wire representation.
+#[async_trait]
+pub trait Provider: Send + Sync {
+    /// Sends `messages` to `model` and returns the text of the reply.
+    async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<String>;
+
+    /// Sends `messages` to `model` and returns a reply conforming to `format`.
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value>;
+
+    /// Sends `messages` to `model` and passes the reply to `on_text` piece by
+    /// piece as it arrives. Providers that can't stream send it in one piece.
+    // The explicit `for<'t>` keeps `async_trait` from tying the pieces'
+    // lifetime to the call.
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<()> {
+        on_text(&self.chat(model, messages).await?);
+        Ok(())
+    }
+}
+
+pub fn provider_from_config(cfg: &JjaiConfig) -> Result<Arc<dyn Provider>> {
+    let kind = cfg.provider();
+    let api_key = || {
+        cfg.api_key().with_context(|| {
+            format!(
+                "missing ai.api-key in jj config or {} env var",
+                kind.api_key_env().unwrap_or_default()
+            )
+        })
+    };
+
+    let provider: Arc<dyn Provider> = match kind {
+        ProviderKind::OpenRouter => {
+            Arc::new(OpenRouterProvider::new(cfg.base_url(), api_key()?)?)
+        }
+        ProviderKind::Anthropic => Arc::new(
+            AnthropicProvider::new(
+                cfg.base_url().unwrap_or(anthropic::DEFAULT_BASE_URL),
+                api_key()?,
+            )
+            .with_max_tokens(cfg.max_output_tokens()),
+        ),
+        ProviderKind::OpenAi => Arc::new(OpenAiProvider::new(
+            cfg.base_url().unwrap_or(openai::DEFAULT_BASE_URL),
+            cfg.api_key(),
+        )),
+        ProviderKind::Mock => {
+            let mock = MockProvider::new(cfg.mock_response());
+            match cfg.mock_responses_file() {
+                Some(path) => Arc::new(mock.with_responses_file(path)?),
+                None => Arc::new(mock),
+            }
+        }
+    };
+    Ok(Arc::new(ThrottledProvider::new(
+        provider,
+        cfg.concurrency(),
+        cfg.max_retries(),
+    )))
+}
 
-use crate::config::JjaiConfig;
+pub async fn structured_output<T: DeserializeOwned>(
+    provider: &dyn Provider,
+    model: &str,
+    messages: &[ChatMessage],
+    format: &Format,
+) -> Result<T> {
+    let value = provider.structured(model, messages, format).await?;
+    Ok(serde_json::from_value(value)?)
+}
 
 fn build_system_prompt(cfg: &JjaiConfig) -> String {
-    format!(
+    let mut prompt = format!(
         "You are an assistant that writes concise, informative commit messages based on code diffs.\n\n{}",
         cfg.standard().prompt_instructions()
-    )
+    );
+    if let Some(project_prompt) = cfg.project_prompt() {
+        prompt.push_str("\n\nProject guidelines:\n");
+        prompt.push_str(project_prompt.trim());
+    }
+    if !cfg.examples().is_empty() {
+        prompt.push_str("\n\nRecent commit messages in this project, match their style:");
+        for example in cfg.examples() {
+            prompt.push_str(&format!("\n---\n{example}"));
+        }
+        prompt.push_str("\n---");
+    }
+    prompt
 }
 
 #[derive(serde::Deserialize)]
@@ -15,9 +169,11 @@ struct MessageOutput {
     message: String,
 }
 
-pub async fn generate_description_for_diff(cfg: &JjaiConfig, diff: &str) -> Result<String> {
-    let client = AsyncOrpheus::new(cfg.api_key());
-
+pub async fn generate_description_for_diff(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    diff: &str,
+) -> Result<String> {
     let message_format = Format::json("message")
         .with_schema(|schema| {
             schema
@@ -28,15 +184,712 @@ pub async fn generate_description_for_diff(cfg: &JjaiConfig, diff: &str) -> Resu
 
     let system_prompt = build_system_prompt(cfg);
 
-    let response = client
-        .chat([Message::system(system_prompt), Message::user(diff)])
-        .model(cfg.model())
-        .response_format(message_format)
-        .send()
-        .await?
-        .content()?
-        .to_string();
+    let output: MessageOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(diff)],
+        &message_format,
+    )
+    .await?;
+    Ok(output.message)
+}
+
+/// Information about a commit that is not visible in its diff.
+#[derive(Debug, Clone, Default)]
+pub struct CommitContext {
+    /// Paths with unresolved conflicts in the commit's tree.
+    pub conflicted_paths: Vec<String>,
+    /// One summary line per parent, set only for merge commits.
+    pub merged_parents: Vec<String>,
+    /// Bookmarks on the commit or on the stack it belongs to.
+    pub bookmarks: Vec<String>,
+    /// Descriptions already chosen for parents in the same stack.
+    pub parent_descriptions: Vec<String>,
+    /// Ticket ids the description must reference.
+    pub tickets: Vec<String>,
+}
+
+impl CommitContext {
+    /// Renders the notes that precede the diff in the prompt, if any.
+    fn render(&self, cfg: &JjaiConfig) -> String {
+        let mut notes = String::new();
+
+        if !self.merged_parents.is_empty() {
+            notes.push_str(
+                "This is a merge commit. The diff is against the automatic merge of its \
+                 parents, so it only shows changes made in the merge itself. Merged parents:\n",
+            );
+            for parent in &self.merged_parents {
+                notes.push_str(&format!("- {parent}\n"));
+            }
+            notes.push_str("Write a message describing the merge.\n\n");
+        }
+
+        if !self.bookmarks.is_empty() {
+            let bookmarks: Vec<_> = self.bookmarks.iter().map(|b| format!("`{b}`")).collect();
+            notes.push_str(&format!(
+                "This commit is part of the bookmark {}.\n",
+                bookmarks.join(", ")
+            ));
+        }
+        if !self.parent_descriptions.is_empty() {
+            notes.push_str("It is part of a stack of commits. Its parents are described as:\n");
+            for description in &self.parent_descriptions {
+                notes.push_str(&format!("---\n{}\n", description.trim_end()));
+            }
+            notes.push_str(
+                "---\nExplain this commit's role in the series, consistent with the parent \
+                 messages but without repeating them.\n",
+            );
+        }
+        if !self.bookmarks.is_empty() || !self.parent_descriptions.is_empty() {
+            notes.push('\n');
+        }
+
+        if !self.tickets.is_empty() {
+            let tickets = self.tickets.join(", ");
+            let placement = match cfg.ticket_placement() {
+                TicketPlacement::Scope => format!("as the scope of the header, e.g. `feat({tickets}): ...`"),
+                TicketPlacement::Prefix => match cfg.standard() {
+                    CommitStandard::Conventional => {
+                        format!("after the type, e.g. `feat: {tickets} ...`")
+                    }
+                    CommitStandard::Gitmoji => format!("after the gitmoji, e.g. `✨ {tickets} ...`"),
+                    _ => format!("at the start of the subject, e.g. `{tickets}: ...`"),
+                },
+                TicketPlacement::Trailer => {
+                    format!("in a `{REFS_TRAILER}: {tickets}` trailer at the end of the message")
+                }
+            };
+            notes.push_str(&format!("Reference {tickets} {placement}.\n\n"));
+        }
+
+        if !self.conflicted_paths.is_empty() {
+            notes.push_str(
+                "These paths have unresolved conflicts, shown with conflict markers in the diff:\n",
+            );
+            for path in &self.conflicted_paths {
+                notes.push_str(&format!("- {path}\n"));
+            }
+            if cfg.conflicts() == ConflictPolicy::Mention {
+                notes.push_str("Mention the unresolved conflicts in the commit message.\n");
+            }
+            notes.push('\n');
+        }
+
+        notes
+    }
+}
+
+/// Generates a description for `patch`, keeping the request within the
+/// configured token budget.
+pub async fn generate_description_for_patch(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    patch: &CommitPatch,
+    context: &CommitContext,
+) -> Result<String> {
+    let notes = context.render(cfg);
+    let diff = if patch.is_empty() {
+        "(no changes beyond merging the parents)\n".to_string()
+    } else {
+        patch.to_string()
+    };
+    // The system prompt carries the project guidelines and examples, which
+    // can take a good part of the budget.
+    let total_budget = cfg
+        .token_budget()
+        .saturating_sub(estimate_tokens(&build_system_prompt(cfg)));
+    let budget = total_budget.saturating_sub(estimate_tokens(&notes));
+    if estimate_tokens(&diff) <= budget {
+        return generate_description_for_diff(provider, cfg, &format!("{notes}{diff}")).await;
+    }
+
+    match cfg.oversize() {
+        OversizeStrategy::Truncate => {
+            let diff = truncate_patch(patch, budget);
+            generate_description_for_diff(provider, cfg, &format!("{notes}{diff}")).await
+        }
+        OversizeStrategy::Chunk => {
+            let chunks = chunk_patch(patch, budget);
+            let summaries = try_join_all(
+                chunks
+                    .iter()
+                    .map(|chunk| summarize_diff_chunk(provider, cfg, chunk)),
+            )
+            .await?;
+
+            let mut prompt = format!(
+                "{notes}The diff for this commit was too large to send at once, so it was \
+                 summarized in parts. Write one commit message for the whole change.\n\n\
+                 Files changed:\n",
+            );
+            // The stats get at most half of the budget, the summaries share
+            // what is left.
+            let stats_budget = total_budget / 2;
+            for (i, file) in patch.files.iter().enumerate() {
+                let stat = format!("{}\n", file.stat());
+                if estimate_tokens(&prompt) + estimate_tokens(&stat) > stats_budget {
+                    prompt.push_str(&format!("... and {} more files\n", patch.files.len() - i));
+                    break;
+                }
+                prompt.push_str(&stat);
+            }
+            let mut remaining = total_budget.saturating_sub(estimate_tokens(&prompt));
+            for (i, summary) in summaries.iter().enumerate() {
+                let header = format!("\nPart {} of {}:\n", i + 1, summaries.len());
+                let share = (remaining / (summaries.len() - i))
+                    .saturating_sub(estimate_tokens(&header) + 1);
+                let part = format!("{header}{}\n", truncate_text(summary, share));
+                remaining = remaining.saturating_sub(estimate_tokens(&part));
+                prompt.push_str(&part);
+            }
+
+            generate_description_for_diff(provider, cfg, &prompt).await
+        }
+    }
+}
+
+async fn summarize_diff_chunk(provider: &dyn Provider, cfg: &JjaiConfig, chunk: &str) -> Result<String> {
+    let system_prompt = "You are summarizing one part of a large code diff so that a commit \
+        message can later be written for the whole change. List what changed as concise \
+        bullet points. Do not write a commit message.";
+
+    provider
+        .chat(
+            cfg.model(),
+            &[ChatMessage::system(system_prompt), ChatMessage::user(chunk)],
+        )
+        .await
+}
+
+/// One commit of a proposed split: its description and the labels of the
+/// changes that go into it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SplitGroup {
+    pub description: String,
+    pub changes: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct SplitOutput {
+    commits: Vec<SplitGroup>,
+}
+
+/// Asks the model to group the labeled changes in `diff` into an ordered
+/// series of commits.
+pub async fn propose_split(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    diff: &str,
+) -> Result<Vec<SplitGroup>> {
+    let tokens = estimate_tokens(diff);
+    if tokens > cfg.token_budget() {
+        anyhow::bail!(
+            "diff is too large to split ({tokens} tokens, budget {})",
+            cfg.token_budget()
+        );
+    }
+
+    let split_format = Format::json("split")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "commits",
+                    Param::array()
+                        .description("The commits to create, oldest first")
+                        .items(
+                            Param::object()
+                                .property(
+                                    "description",
+                                    Param::string().description("The commit message"),
+                                )
+                                .property(
+                                    "changes",
+                                    Param::array()
+                                        .description("Labels of the changes in this commit")
+                                        .items(Param::string()),
+                                )
+                                .required(["description", "changes"]),
+                        ),
+                )
+                .required(["commits"])
+        })
+        .build();
+
+    let system_prompt = format!(
+        "{}\n\nThe user's change mixes several unrelated concerns. Split it into a series of \
+         small, logical commits, such as a refactor, a bug fix and a feature, ordered so that \
+         each commit builds on the previous ones. Every file or hunk in the diff is preceded by \
+         a label in square brackets. Assign every label to exactly one commit and write a \
+         commit message for each commit.",
+        build_system_prompt(cfg)
+    );
+
+    let output: SplitOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(diff)],
+        &split_format,
+    )
+    .await?;
+    Ok(output.commits)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A review comment on a line range of a file in the new tree.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Finding {
+    pub path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct ReviewOutput {
+    findings: Vec<RawFinding>,
+}
+
+/// A finding as the model returned it. Line numbers are checked afterwards,
+/// so that one bad number doesn't fail the whole review.
+#[derive(serde::Deserialize)]
+struct RawFinding {
+    path: String,
+    start_line: Option<i64>,
+    end_line: Option<i64>,
+    severity: Severity,
+    message: String,
+}
+
+impl From<RawFinding> for Finding {
+    /// Lines below 1 count as missing, a missing bound takes the other's
+    /// value, and a reversed range is swapped.
+    fn from(raw: RawFinding) -> Self {
+        let line = |line: Option<i64>| {
+            line.and_then(|line| usize::try_from(line).ok())
+                .filter(|&line| line >= 1)
+        };
+        let start = line(raw.start_line);
+        let end = line(raw.end_line);
+        let start_line = start.or(end).unwrap_or(1);
+        let end_line = end.unwrap_or(start_line);
+        Finding {
+            path: raw.path,
+            start_line: start_line.min(end_line),
+            end_line: start_line.max(end_line),
+            severity: raw.severity,
+            message: raw.message,
+        }
+    }
+}
+
+/// Asks the model to review `diff`, whose lines are prefixed with their line
+/// number in the new tree.
+pub async fn review_diff(provider: &dyn Provider, cfg: &JjaiConfig, diff: &str) -> Result<Vec<Finding>> {
+    let review_format = Format::json("review")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "findings",
+                    Param::array().items(
+                        Param::object()
+                            .property("path", Param::string().description("File path as in the diff"))
+                            .property(
+                                "start_line",
+                                Param::integer().description("First line in the new file"),
+                            )
+                            .property(
+                                "end_line",
+                                Param::integer().description("Last line in the new file"),
+                            )
+                            .property(
+                                "severity",
+                                Param::string().enums(["info", "warning", "error"]),
+                            )
+                            .property("message", Param::string().description("What is wrong and how to fix it"))
+                            .required(["path", "start_line", "end_line", "severity", "message"]),
+                    ),
+                )
+                .required(["findings"])
+        })
+        .build();
+
+    let system_prompt = "You are an experienced code reviewer. Review the diff and report \
+        concrete problems such as bugs, security issues, missing error handling, performance \
+        pitfalls and confusing code. Each line of the diff starts with its line number in the \
+        new version of the file; use those numbers for line ranges. Use `error` for bugs that \
+        must be fixed, `warning` for likely problems and `info` for suggestions. Return no \
+        findings if the change looks good.";
+
+    let output: ReviewOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(diff)],
+        &review_format,
+    )
+    .await?;
+    Ok(output.findings.into_iter().map(Finding::from).collect())
+}
 
-    let output = serde_json::from_str::<MessageOutput>(&response)?;
+/// One line of a changelog and the change ids it summarizes.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ChangelogEntry {
+    pub section: String,
+    pub text: String,
+    pub change_ids: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct ChangelogOutput {
+    entries: Vec<ChangelogEntry>,
+}
+
+/// Asks the model to summarize `commits` as changelog entries, each filed
+/// under one of `sections`.
+pub async fn write_changelog(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    sections: &[&str],
+    commits: &str,
+) -> Result<Vec<ChangelogEntry>> {
+    let changelog_format = Format::json("changelog")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "entries",
+                    Param::array().items(
+                        Param::object()
+                            .property("section", Param::string().enums(sections.iter().copied()))
+                            .property(
+                                "text",
+                                Param::string().description("One line describing the change for users"),
+                            )
+                            .property(
+                                "change_ids",
+                                Param::array()
+                                    .description("Change ids of the commits this entry covers")
+                                    .items(Param::string()),
+                            )
+                            .required(["section", "text", "change_ids"]),
+                    ),
+                )
+                .required(["entries"])
+        })
+        .build();
+
+    let system_prompt = format!(
+        "You are writing release notes from a list of commits. Write one concise, \
+         user-facing changelog entry per notable change, merging commits that belong to the \
+         same change and leaving out purely internal ones. File every entry under one of \
+         these sections: {}. When a commit has a Conventional Commits type, use the section \
+         that matches its type. Cite the change ids of the commits each entry covers.",
+        sections.join(", ")
+    );
+
+    let output: ChangelogOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(commits)],
+        &changelog_format,
+    )
+    .await?;
+    Ok(output.entries)
+}
+
+/// The model's parts of a bookmark name.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BookmarkSuggestion {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub slug: String,
+}
+
+/// Asks the model for the type and a short slug naming the change in
+/// `prompt`.
+pub async fn suggest_bookmark(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    prompt: &str,
+) -> Result<BookmarkSuggestion> {
+    let bookmark_format = Format::json("bookmark")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "type",
+                    Param::string()
+                        .description("Kind of change")
+                        .enums(crate::conventional::TYPES.iter().map(|(kind, _)| *kind)),
+                )
+                .property(
+                    "slug",
+                    Param::string().description("Two to five lowercase words joined by hyphens"),
+                )
+                .required(["type", "slug"])
+        })
+        .build();
+
+    let system_prompt = "You name git branches. Given a change's description and diff, \
+        classify the change and write a short, specific slug for it, like `oauth-login` or \
+        `fix-empty-config`.";
+
+    structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(prompt)],
+        &bookmark_format,
+    )
+    .await
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullRequest {
+    pub title: String,
+    pub body: String,
+}
+
+/// Asks the model for a pull request title and Markdown body for the
+/// commits in `commits`, filling in `template` if the repository has one.
+pub async fn write_pull_request(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    commits: &str,
+    template: Option<&str>,
+) -> Result<PullRequest> {
+    let pr_format = Format::json("pull_request")
+        .with_schema(|schema| {
+            schema
+                .property("title", Param::string().description("Short pull request title"))
+                .property("body", Param::string().description("Pull request body in Markdown"))
+                .required(["title", "body"])
+        })
+        .build();
+
+    let mut system_prompt = String::from(
+        "You write pull request descriptions for a series of commits. The title is a short \
+         summary of the whole series. ",
+    );
+    match template {
+        Some(template) => system_prompt.push_str(&format!(
+            "The body must fill in the repository's pull request template below, keeping its \
+             headings and checklists and leaving out instructions meant for the author. \
+             Mention each commit by its change id where relevant.\n\n{template}"
+        )),
+        None => system_prompt.push_str(
+            "The body is Markdown with a `## Summary` section explaining what the series does \
+             and why, a `## Commits` section with one bullet per commit starting with its \
+             change id, and a `## Testing` section with notes on how the change was or should \
+             be tested.",
+        ),
+    }
+
+    structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(commits)],
+        &pr_format,
+    )
+    .await
+}
+
+/// Streams a plain-English explanation of a commit to `on_text`.
+pub async fn explain_commit(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    commit: &str,
+    on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+) -> Result<()> {
+    let system_prompt = "You explain commits to developers who are new to the codebase. Given \
+                         a commit's description and diff, explain in plain English what the \
+                         change does, why it was likely made, and how it affects the \
+                         surrounding code. Refer to files and functions by name. Answer in \
+                         plain text paragraphs without Markdown headings.";
+
+    provider
+        .chat_stream(
+            cfg.model(),
+            &[ChatMessage::system(system_prompt), ChatMessage::user(commit)],
+            on_text,
+        )
+        .await
+}
+
+#[derive(serde::Deserialize)]
+struct ResolutionOutput {
+    resolution: String,
+}
+
+/// Asks the model to merge the sides of a conflicted file, returning the
+/// full resolved content.
+pub async fn resolve_conflict(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    conflict: &str,
+) -> Result<String> {
+    let resolution_format = Format::json("resolution")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "resolution",
+                    Param::string().description("The complete resolved file content"),
+                )
+                .required(["resolution"])
+        })
+        .build();
+
+    let system_prompt = "You resolve merge conflicts. You are given the base version(s) of a \
+                         file and each conflicting side. Each side changes the base; combine \
+                         the intent of all sides into a single version of the file. Return the \
+                         complete file content without conflict markers, code fences or \
+                         commentary.";
+
+    let output: ResolutionOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(conflict)],
+        &resolution_format,
+    )
+    .await?;
+    Ok(output.resolution)
+}
+
+/// Rewrites an existing description to follow the configured standard while
+/// keeping what it says. `problems` lists known violations to fix.
+pub async fn improve_description(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    description: &str,
+    problems: &[String],
+    patch: &CommitPatch,
+    context: &CommitContext,
+) -> Result<String> {
+    let message_format = Format::json("message")
+        .with_schema(|schema| {
+            schema
+                .property("message", Param::string().description("The commit message"))
+                .required(["message"])
+        })
+        .build();
+
+    let system_prompt = format!(
+        "{}\n\nYou are given an existing commit message and the diff it describes. Rewrite \
+         the message so it follows these rules, keeping its meaning and any details that are \
+         still accurate. Correct statements the diff contradicts.",
+        build_system_prompt(cfg)
+    );
+
+    let mut prompt = context.render(cfg);
+    prompt.push_str(&format!("Current message:\n{}\n\n", description.trim()));
+    if !problems.is_empty() {
+        prompt.push_str("Problems to fix:\n");
+        for problem in problems {
+            prompt.push_str(&format!("- {problem}\n"));
+        }
+        prompt.push('\n');
+    }
+    let budget = cfg
+        .token_budget()
+        .saturating_sub(estimate_tokens(&system_prompt) + estimate_tokens(&prompt));
+    prompt.push_str(&fit_patch(patch, budget));
+
+    let output: MessageOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(prompt)],
+        &message_format,
+    )
+    .await?;
     Ok(output.message)
 }
+
+/// Streams an answer to `question` based on the commits in `commits`.
+pub async fn answer_question(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    question: &str,
+    commits: &str,
+    on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+) -> Result<()> {
+    let system_prompt = "You answer questions about a repository's history using only the \
+                         commits given, each introduced by `Commit <change id> (<date>)`. Cite \
+                         the commits your answer relies on by change id in square brackets, \
+                         like [kxqvlpmt]. If the commits don't answer the question, say so \
+                         instead of guessing. Answer in plain text.";
+
+    provider
+        .chat_stream(
+            cfg.model(),
+            &[
+                ChatMessage::system(system_prompt),
+                ChatMessage::user(format!("{commits}Question: {question}")),
+            ],
+            on_text,
+        )
+        .await
+}
+
+/// A commit the model thinks belongs in an earlier one.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SquashSuggestion {
+    pub source: String,
+    pub target: String,
+    pub reason: String,
+}
+
+#[derive(serde::Deserialize)]
+struct SquashOutput {
+    squashes: Vec<SquashSuggestion>,
+}
+
+/// Asks the model which of the candidate squashes listed in `prompt` are
+/// fixups that belong in their target.
+pub async fn suggest_squashes(
+    provider: &dyn Provider,
+    cfg: &JjaiConfig,
+    prompt: &str,
+) -> Result<Vec<SquashSuggestion>> {
+    let squash_format = Format::json("squashes")
+        .with_schema(|schema| {
+            schema
+                .property(
+                    "squashes",
+                    Param::array().items(
+                        Param::object()
+                            .property("source", Param::string().description("Change id of the fixup commit"))
+                            .property(
+                                "target",
+                                Param::string().description("Change id of the commit it belongs in"),
+                            )
+                            .property("reason", Param::string().description("One short sentence"))
+                            .required(["source", "target", "reason"]),
+                    ),
+                )
+                .required(["squashes"])
+        })
+        .build();
+
+    let system_prompt = "You tidy up stacks of commits before review. Given the commits of a \
+                         stack, oldest first, and candidate squashes found by comparing the \
+                         files and lines they touch, pick the candidates where the later \
+                         commit is a fixup that only corrects or completes the earlier one, \
+                         such as fixing a typo, addressing review feedback or adding a \
+                         forgotten file. Leave out commits that make a separate change of \
+                         their own. Only pick from the candidates.";
+
+    let output: SquashOutput = structured_output(
+        provider,
+        cfg.model(),
+        &[ChatMessage::system(system_prompt), ChatMessage::user(prompt)],
+        &squash_format,
+    )
+    .await?;
+    Ok(output.squashes)
+}
@@ -0,0 +1,229 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use orpheus::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{sse, ApiError, ChatMessage, Provider, Role};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+const API_VERSION: &str = "2023-06-01";
+const DEFAULT_MAX_TOKENS: u32 = 4096;
+
+pub struct AnthropicProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: String,
+    max_tokens: u32,
+}
+
+impl AnthropicProvider {
+    pub fn new(base_url: &str, api_key: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            max_tokens: DEFAULT_MAX_TOKENS,
+        }
+    }
+
+    /// Sets the cap on generated tokens, which the Messages API requires.
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    fn request_body(&self, model: &str, messages: &[ChatMessage]) -> serde_json::Value {
+        request_body(model, messages, self.max_tokens)
+    }
+
+    async fn post(&self, body: serde_json::Value) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&body)
+            .send()
+            .await
+            .context("failed to send request to Anthropic")?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ApiError {
+                service: "Anthropic API",
+                status,
+                body: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(response)
+    }
+
+    async fn send(&self, body: serde_json::Value) -> Result<MessagesResponse> {
+        let response = self
+            .post(body)
+            .await?
+            .json::<MessagesResponse>()
+            .await
+            .context("failed to parse Anthropic response")?;
+        check_stop_reason(response.stop_reason.as_deref())?;
+        Ok(response)
+    }
+}
+
+/// A response cut off at `max_tokens` would pass for a complete one, such
+/// as a resolved file missing its end.
+fn check_stop_reason(stop_reason: Option<&str>) -> Result<()> {
+    if stop_reason == Some("max_tokens") {
+        bail!("Anthropic response was cut off at the output limit, raise ai.max-output-tokens");
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct MessagesResponse {
+    content: Vec<ContentBlock>,
+    stop_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ContentBlock {
+    Text { text: String },
+    ToolUse { input: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StreamEvent {
+    ContentBlockDelta { delta: Delta },
+    MessageDelta { delta: MessageDelta },
+    Error { error: serde_json::Value },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Delta {
+    TextDelta { text: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct MessageDelta {
+    stop_reason: Option<String>,
+}
+
+/// Anthropic takes the system prompt as a top-level field rather than as a
+/// message, so system messages are split out and joined.
+fn request_body(model: &str, messages: &[ChatMessage], max_tokens: u32) -> serde_json::Value {
+    let system = messages
+        .iter()
+        .filter(|m| m.role == Role::System)
+        .map(|m| m.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let messages: Vec<_> = messages
+        .iter()
+        .filter_map(|m| {
+            let role = match m.role {
+                Role::System => return None,
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            Some(json!({ "role": role, "content": m.content }))
+        })
+        .collect();
+
+    let mut body = json!({
+        "model": model,
+        "max_tokens": max_tokens,
+        "messages": messages,
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system);
+    }
+    body
+}
+
+#[async_trait]
+impl Provider for AnthropicProvider {
+    async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<String> {
+        let response = self.send(self.request_body(model, messages)).await?;
+
+        let text: String = response
+            .content
+            .into_iter()
+            .filter_map(|block| match block {
+                ContentBlock::Text { text } => Some(text),
+                _ => None,
+            })
+            .collect();
+
+        Ok(text)
+    }
+
+    /// Structured output is implemented by forcing the model to call a single
+    /// tool whose input schema is the requested format.
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value> {
+        let Format::JsonSchema { name, schema, .. } = format;
+
+        let mut body = self.request_body(model, messages);
+        body["tools"] = json!([{
+            "name": name,
+            "description": "Respond with the requested structured output",
+            "input_schema": schema,
+        }]);
+        body["tool_choice"] = json!({ "type": "tool", "name": name });
+
+        let response = self.send(body).await?;
+
+        response
+            .content
+            .into_iter()
+            .find_map(|block| match block {
+                ContentBlock::ToolUse { input } => Some(input),
+                _ => None,
+            })
+            .context("Anthropic response did not contain structured output")
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<()> {
+        let mut body = self.request_body(model, messages);
+        body["stream"] = json!(true);
+
+        let response = self.post(body).await?;
+        sse::for_each_data(response, |data| {
+            let event: StreamEvent =
+                serde_json::from_str(data).context("failed to parse Anthropic stream event")?;
+            match event {
+                StreamEvent::ContentBlockDelta {
+                    delta: Delta::TextDelta { text },
+                } => on_text(&text),
+                StreamEvent::MessageDelta { delta } => {
+                    check_stop_reason(delta.stop_reason.as_deref())?
+                }
+                StreamEvent::Error { error } => bail!("Anthropic stream error: {error}"),
+                _ => {}
+            }
+            Ok(())
+        })
+        .await
+    }
+}
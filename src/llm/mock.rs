@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use orpheus::models::chat::{Param, ParamType};
+use orpheus::prelude::*;
+use sha2::{Digest, Sha256};
+
+use super::{ChatMessage, Provider, Role};
+
+/// Deterministic provider for tests and offline experiments.
+///
+/// Responses are looked up by [`diff_hash`] of the diff in the last user
+/// message, falling back to a fixed response. Every request is recorded so tests can assert on
+/// the prompts that were sent.
+#[derive(Default)]
+pub struct MockProvider {
+    fixed: Option<String>,
+    scripted: HashMap<String, serde_json::Value>,
+    requests: Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+impl MockProvider {
+    pub fn new(fixed: Option<&str>) -> Self {
+        Self {
+            fixed: fixed.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    /// Loads scripted responses from a JSON object mapping diff hashes to
+    /// either a string or a structured JSON value.
+    pub fn with_responses_file(mut self, path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read mock responses '{}'", path.display()))?;
+        self.scripted = serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse mock responses '{}'", path.display()))?;
+        Ok(self)
+    }
+
+    pub fn with_response(mut self, diff: &str, response: impl Into<serde_json::Value>) -> Self {
+        self.scripted.insert(diff_hash(diff), response.into());
+        self
+    }
+
+    /// All requests received so far, in order.
+    pub fn requests(&self) -> Vec<Vec<ChatMessage>> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn respond(&self, messages: &[ChatMessage]) -> Result<serde_json::Value> {
+        self.requests.lock().unwrap().push(messages.to_vec());
+
+        let prompt = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::User)
+            .map(|m| m.content.as_str())
+            .unwrap_or_default();
+        let hash = diff_hash(diff_section(prompt));
+
+        if let Some(response) = self.scripted.get(&hash) {
+            return Ok(response.clone());
+        }
+        match &self.fixed {
+            Some(fixed) => Ok(serde_json::Value::String(fixed.clone())),
+            None => bail!("no mock response for diff hash {hash}"),
+        }
+    }
+}
+
+/// The diff at the end of `prompt`, without the notes before it, so scripted
+/// responses don't depend on the context sent along. Prompts without a diff
+/// are used whole.
+fn diff_section(prompt: &str) -> &str {
+    if prompt.starts_with("diff --git ") {
+        return prompt;
+    }
+    match prompt.find("\ndiff --git ") {
+        Some(index) => &prompt[index + 1..],
+        None => prompt,
+    }
+}
+
+/// Hex-encoded SHA-256 of `text`, used to key scripted mock responses.
+pub fn diff_hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+#[async_trait]
+impl Provider for MockProvider {
+    async fn chat(&self, _model: &str, messages: &[ChatMessage]) -> Result<String> {
+        match self.respond(messages)? {
+            serde_json::Value::String(text) => Ok(text),
+            value => Ok(value.to_string()),
+        }
+    }
+
+    /// Plain string responses are wrapped into the schema's only required
+    /// property, so a single fixed message works for simple formats.
+    async fn structured(
+        &self,
+        _model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value> {
+        let text = match self.respond(messages)? {
+            serde_json::Value::String(text) => text,
+            value => return Ok(value),
+        };
+
+        if let Ok(value @ serde_json::Value::Object(_)) = serde_json::from_str(&text) {
+            return Ok(value);
+        }
+
+        let Format::JsonSchema { schema, .. } = format;
+        match schema {
+            Param::Object {
+                required: Some(required),
+                properties,
+                ..
+            } if required.len() == 1
+                && matches!(
+                    properties.get(&required[0]),
+                    Some(ParamType::Simple(Param::String { .. }))
+                ) =>
+            {
+                Ok(serde_json::json!({ required[0].as_str(): text }))
+            }
+            _ => bail!("mock response is not valid JSON for the requested format"),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use orpheus::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{sse, ApiError, ChatMessage, Provider, Role};
+
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+/// Provider for any server speaking the OpenAI chat-completions API, such as
+/// OpenAI itself, vLLM, llama.cpp's `llama-server` or Ollama.
+pub struct OpenAiProvider {
+    client: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiProvider {
+    pub fn new(base_url: &str, api_key: Option<&str>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(str::to_string),
+        }
+    }
+
+    async fn post(&self, body: serde_json::Value) -> Result<reqwest::Response> {
+        let mut request = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&body);
+
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("failed to send request to {}", self.base_url))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ApiError {
+                service: "chat completion",
+                status,
+                body: response.text().await.unwrap_or_default(),
+            }
+            .into());
+        }
+        Ok(response)
+    }
+
+    async fn send(&self, body: serde_json::Value) -> Result<String> {
+        let completion = self
+            .post(body)
+            .await?
+            .json::<ChatCompletion>()
+            .await
+            .context("failed to parse chat completion response")?;
+
+        let choice = completion
+            .choices
+            .into_iter()
+            .next()
+            .context("chat completion response contained no choices")?;
+        check_finish_reason(choice.finish_reason.as_deref())?;
+        choice
+            .message
+            .content
+            .context("chat completion response contained no content")
+    }
+}
+
+/// A response cut off at the output limit would pass for a complete one.
+pub(super) fn check_finish_reason(finish_reason: Option<&str>) -> Result<()> {
+    if finish_reason == Some("length") {
+        bail!("chat completion was cut off at the model's output limit");
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct ChatCompletion {
+    choices: Vec<Choice>,
+}
+
+#[derive(Deserialize)]
+struct Choice {
+    message: ResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct StreamChunk {
+    choices: Vec<StreamChoice>,
+}
+
+#[derive(Deserialize)]
+struct StreamChoice {
+    delta: ResponseMessage,
+    finish_reason: Option<String>,
+}
+
+fn request_body(model: &str, messages: &[ChatMessage]) -> serde_json::Value {
+    let messages: Vec<_> = messages
+        .iter()
+        .map(|m| {
+            let role = match m.role {
+                Role::System => "system",
+                Role::User => "user",
+                Role::Assistant => "assistant",
+            };
+            json!({ "role": role, "content": m.content })
+        })
+        .collect();
+
+    json!({
+        "model": model,
+        "messages": messages,
+        "stream": false,
+    })
+}
+
+#[async_trait]
+impl Provider for OpenAiProvider {
+    async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<String> {
+        self.send(request_body(model, messages)).await
+    }
+
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value> {
+        let mut body = request_body(model, messages);
+        // `Format` already serializes to the `response_format` wire shape.
+        body["response_format"] = serde_json::to_value(format)?;
+
+        let content = self.send(body).await?;
+        serde_json::from_str(&content).context("model did not return valid JSON")
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<()> {
+        let mut body = request_body(model, messages);
+        body["stream"] = json!(true);
+
+        let response = self.post(body).await?;
+        sse::for_each_data(response, |data| {
+            let chunk: StreamChunk =
+                serde_json::from_str(data).context("failed to parse streamed chat completion")?;
+            for choice in chunk.choices {
+                if let Some(content) = choice.delta.content {
+                    on_text(&content);
+                }
+                check_finish_reason(choice.finish_reason.as_deref())?;
+            }
+            Ok(())
+        })
+        .await
+    }
+}
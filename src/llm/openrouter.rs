@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use futures::StreamExt;
+use orpheus::models::chat::ChatCompletion;
+use orpheus::prelude::*;
+
+use super::openai::check_finish_reason;
+use super::{ChatMessage, Provider, Role};
+
+pub struct OpenRouterProvider {
+    client: AsyncOrpheus,
+}
+
+impl OpenRouterProvider {
+    pub fn new(base_url: Option<&str>, api_key: &str) -> Result<Self> {
+        let client = match base_url {
+            Some(url) => {
+                let url = reqwest::Url::parse(url)
+                    .with_context(|| format!("invalid ai.base-url '{url}'"))?;
+                AsyncOrpheus::builder()
+                    .base_url(url)
+                    .api_key(api_key)
+                    .build()
+            }
+            None => AsyncOrpheus::new(api_key),
+        };
+        Ok(Self { client })
+    }
+}
+
+fn to_orpheus_messages(messages: &[ChatMessage]) -> Vec<Message> {
+    messages
+        .iter()
+        .map(|message| match message.role {
+            Role::System => Message::system(message.content.as_str()),
+            Role::User => Message::user(message.content.as_str()),
+            Role::Assistant => Message::assistant(message.content.as_str()),
+        })
+        .collect()
+}
+
+fn check_completion(completion: &ChatCompletion) -> Result<()> {
+    for choice in &completion.choices {
+        check_finish_reason(Some(&choice.finish_reason))?;
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Provider for OpenRouterProvider {
+    async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<String> {
+        let completion = self
+            .client
+            .chat(to_orpheus_messages(messages))
+            .model(model)
+            .send()
+            .await?;
+        check_completion(&completion)?;
+
+        Ok(completion.content()?.to_string())
+    }
+
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value> {
+        let completion = self
+            .client
+            .chat(to_orpheus_messages(messages))
+            .model(model)
+            .response_format(format.clone())
+            .send()
+            .await?;
+        check_completion(&completion)?;
+
+        Ok(serde_json::from_str(&completion.content()?.to_string())?)
+    }
+
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<()> {
+        let mut stream = self
+            .client
+            .chat(to_orpheus_messages(messages))
+            .model(model)
+            .stream()
+            .await?;
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            on_text(&chunk.content()?.to_string());
+            for choice in &chunk.choices {
+                check_finish_reason(choice.finish_reason.as_deref())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use anyhow::{Context, Result};
+
+/// Calls `on_data` with the payload of every `data:` line of a server-sent
+/// events response, stopping at OpenAI's `[DONE]` marker.
+pub(super) async fn for_each_data(
+    mut response: reqwest::Response,
+    mut on_data: impl FnMut(&str) -> Result<()>,
+) -> Result<()> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .context("failed to read streamed response")?
+    {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let Some(data) = line.trim_end().strip_prefix("data:") else {
+                continue;
+            };
+            let data = data.trim_start();
+            if data == "[DONE]" {
+                return Ok(());
+            }
+            on_data(data)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use orpheus::prelude::*;
+use reqwest::StatusCode;
+use tokio::sync::Semaphore;
+
+use super::{ChatMessage, Provider};
+
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+
+/// An unsuccessful HTTP response from a model API.
+#[derive(Debug)]
+pub struct ApiError {
+    pub service: &'static str,
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} error {}: {}", self.service, self.status, self.body)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Whether `err` is a rate limit or server error that is worth retrying.
+fn is_retryable(err: &anyhow::Error) -> bool {
+    let status = if let Some(err) = err.downcast_ref::<ApiError>() {
+        err.status.as_u16()
+    } else {
+        match err.downcast_ref::<orpheus::Error>() {
+            Some(orpheus::Error::OpenRouter { code, .. }) => *code,
+            Some(orpheus::Error::Unexpected { status, .. }) => *status,
+            _ => return false,
+        }
+    };
+    status == StatusCode::TOO_MANY_REQUESTS.as_u16() || (500..600).contains(&status)
+}
+
+/// Limits the number of requests in flight to the wrapped provider and
+/// retries rate-limited or failed requests with exponential backoff.
+pub struct ThrottledProvider {
+    inner: Arc<dyn Provider>,
+    permits: Semaphore,
+    max_retries: u32,
+}
+
+impl ThrottledProvider {
+    pub fn new(inner: Arc<dyn Provider>, concurrency: usize, max_retries: u32) -> Self {
+        Self {
+            inner,
+            permits: Semaphore::new(concurrency),
+            max_retries,
+        }
+    }
+
+    async fn call<T, F, Fut>(&self, mut request: F) -> Result<T>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<T>> + Send,
+    {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            // The permit is released while backing off so other requests
+            // can make progress.
+            let result = {
+                let _permit = self.permits.acquire().await?;
+                request().await
+            };
+            match result {
+                Err(err) if attempt < self.max_retries && is_retryable(&err) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Provider for ThrottledProvider {
+    async fn chat(&self, model: &str, messages: &[ChatMessage]) -> Result<String> {
+        self.call(|| self.inner.chat(model, messages)).await
+    }
+
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        format: &Format,
+    ) -> Result<serde_json::Value> {
+        self.call(|| self.inner.structured(model, messages, format))
+            .await
+    }
+
+    /// Only retries while nothing has been streamed yet; once text has been
+    /// passed on, a failure is returned as is.
+    async fn chat_stream(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        on_text: &mut (dyn for<'t> FnMut(&'t str) + Send),
+    ) -> Result<()> {
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let mut streamed = false;
+            let result = {
+                let _permit = self.permits.acquire().await?;
+                let mut forward = |text: &str| {
+                    streamed = true;
+                    on_text(text);
+                };
+                self.inner.chat_stream(model, messages, &mut forward).await
+            };
+            match result {
+                Err(err) if !streamed && attempt < self.max_retries && is_retryable(&err) => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -1,8 +1,11 @@
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
 use std::process::ExitCode;
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 use owo_colors::OwoColorize;
-use jj_ai::command::CommandContext;
+use jj_ai::command::{ChangelogFormat, CommandContext, DescribeMode};
+use jj_ai::config::ConflictPolicy;
 
 #[derive(Parser)]
 #[command(name = "jj-ai")]
@@ -28,10 +31,152 @@ enum Command {
         #[arg(long)]
         overwrite: bool,
 
+        /// Fix existing descriptions up to the standard instead of replacing them
+        #[arg(long, conflicts_with = "overwrite")]
+        improve: bool,
+
         /// Open the generated description in an editor before applying
         #[arg(long)]
         editor: bool,
     },
+    /// Split a commit into logical commits proposed by an LLM
+    Split {
+        /// The revision to split
+        #[arg(short, long, default_value = "@")]
+        revision: String,
+
+        /// Show the proposed split without applying it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Apply the split without asking for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Check descriptions against the configured commit standard
+    Lint {
+        /// The revisions to check
+        #[arg(short, long, default_value = "trunk()..@")]
+        revision: String,
+
+        /// Propose rewritten descriptions for failing commits using an LLM
+        #[arg(long)]
+        fix: bool,
+
+        /// Apply the rewritten descriptions without asking for confirmation
+        #[arg(long, requires = "fix")]
+        yes: bool,
+    },
+    /// Resolve conflicts in a revision using an LLM
+    Resolve {
+        /// The revision whose conflicts to resolve
+        #[arg(short, long, default_value = "@")]
+        revision: String,
+
+        /// Show the proposed resolutions without writing them
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Write the resolutions without asking for confirmation
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Find fixup commits in a stack and suggest where to squash them
+    SquashSuggest {
+        /// The revisions to analyze
+        #[arg(short, long, default_value = "trunk()..@")]
+        revision: String,
+
+        /// Squash the suggested commits after confirmation
+        #[arg(long)]
+        apply: bool,
+
+        /// Squash without asking for confirmation
+        #[arg(long, requires = "apply")]
+        yes: bool,
+    },
+    /// Create a bookmark with a generated name for a change
+    Bookmark {
+        /// The revision to create the bookmark at
+        #[arg(short, long, default_value = "@")]
+        revision: String,
+
+        /// Show the generated name without creating the bookmark
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Answer a question about the repository's history using an LLM
+    Ask {
+        /// The question, e.g. "when did we switch to StackedConfig and why?"
+        question: String,
+
+        /// The revisions to search
+        #[arg(short, long, default_value = "::@")]
+        revision: String,
+    },
+    /// Explain what a revision does in plain English using an LLM
+    Explain {
+        /// The revision to explain
+        #[arg(short, long, default_value = "@")]
+        revision: String,
+
+        /// Only explain changes to files matching this fileset
+        fileset: Option<String>,
+    },
+    /// Write release notes for a range of revisions using an LLM
+    Changelog {
+        /// The revisions to include, e.g. 'v1.2..v1.3'
+        #[arg(short, long)]
+        revision: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ChangelogFormat::Markdown)]
+        format: ChangelogFormat,
+
+        /// Release name for the section heading
+        #[arg(long)]
+        title: Option<String>,
+    },
+    /// Write a pull request title and body for a stack using an LLM
+    Pr {
+        /// The revisions in the pull request
+        #[arg(short, long, default_value = "trunk()..@")]
+        revision: String,
+
+        /// Write the pull request to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Review the changes in a revset using an LLM
+    Review {
+        /// The revisions to review
+        #[arg(short, long, default_value = "@")]
+        revision: String,
+
+        /// Output format
+        #[arg(long, value_enum, default_value_t = ReviewFormat::Text)]
+        format: ReviewFormat,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ReviewFormat {
+    /// Annotated diff for the terminal
+    Text,
+    Json,
+    /// SARIF 2.1.0 for code scanning tools
+    Sarif,
+}
+
+/// Asks a yes/no question on stderr, defaulting to no.
+fn confirm(question: &str) -> bool {
+    eprint!("{question} [y/N] ");
+    let _ = std::io::stderr().flush();
+    let mut answer = String::new();
+    if std::io::stdin().lock().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
 #[tokio::main]
@@ -47,16 +192,44 @@ async fn main() -> ExitCode {
     };
 
     match args.command {
-        Command::Describe { revision, dry_run, overwrite, editor } => {
-            match jj_ai::command::run_describe(ctx, &revision, dry_run, overwrite, editor).await {
+        Command::Describe { revision, dry_run, overwrite, improve, editor } => {
+            let warn_conflicts = ctx.cfg.conflicts() == ConflictPolicy::Warn;
+            let mode = if improve {
+                DescribeMode::Improve
+            } else if overwrite {
+                DescribeMode::Overwrite
+            } else {
+                DescribeMode::Fill
+            };
+            match jj_ai::command::run_describe(ctx, &revision, dry_run, mode, editor).await {
                 Ok(result) => {
+                    if result.skipped_conflicted > 0 {
+                        eprintln!(
+                            "Skipped {} commit(s) with unresolved conflicts",
+                            result.skipped_conflicted.yellow()
+                        );
+                    }
+
+                    for item in result
+                        .described
+                        .iter()
+                        .filter(|item| warn_conflicts && !item.conflicted_paths.is_empty())
+                    {
+                        eprintln!(
+                            "{} {} has unresolved conflicts in: {}",
+                            "Warning:".yellow(),
+                            &item.change_id[..8],
+                            item.conflicted_paths.join(", ")
+                        );
+                    }
+
                     if result.described.is_empty() {
                         if result.skipped_existing > 0 {
                             eprintln!(
-                                "Skipped {} commit(s) with existing descriptions (use --overwrite to replace)",
+                                "Skipped {} commit(s) with existing descriptions (use --overwrite to replace or --improve to refine)",
                                 result.skipped_existing.red()
                             );
-                        } else {
+                        } else if result.skipped_conflicted == 0 {
                             eprintln!("No changes in commits, nothing to describe");
                         }
                         return ExitCode::SUCCESS;
@@ -64,8 +237,23 @@ async fn main() -> ExitCode {
 
                     if dry_run {
                         for item in &result.described {
-                            println!("--- {} ---", &item.commit_id[..12]);
-                            println!("{}", item.description);
+                            println!("--- {} ---", &item.change_id[..8]);
+                            match &item.previous_description {
+                                Some(previous) => {
+                                    let patch = jj_ai::diff::text_patch(
+                                        "description",
+                                        previous.as_bytes(),
+                                        item.description.as_bytes(),
+                                    );
+                                    if patch.hunks.is_empty() {
+                                        println!("(unchanged)");
+                                    }
+                                    for hunk in &patch.hunks {
+                                        print!("{hunk}");
+                                    }
+                                }
+                                None => println!("{}", item.description),
+                            }
                             println!();
                         }
                     } else {
@@ -84,5 +272,297 @@ async fn main() -> ExitCode {
                 }
             }
         }
+        Command::Split { revision, dry_run, yes } => {
+            let plan = match jj_ai::command::plan_split(&ctx, &revision).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            if plan.commits.len() < 2 {
+                eprintln!("The model kept the change as a single commit, nothing to split");
+                return ExitCode::SUCCESS;
+            }
+
+            for (index, commit) in plan.commits.iter().enumerate() {
+                println!("--- {}/{} ---", index + 1, plan.commits.len());
+                println!("{}", commit.description.trim_end());
+                println!();
+                for change in &commit.changes {
+                    match &change.hunks {
+                        Some(hunks) => {
+                            let hunks: Vec<String> = hunks.iter().map(|h| (h + 1).to_string()).collect();
+                            println!("  {} (hunks {})", change.path, hunks.join(", "));
+                        }
+                        None => println!("  {}", change.path),
+                    }
+                }
+                println!();
+            }
+
+            if dry_run || (!yes && !confirm("Apply this split?")) {
+                return ExitCode::SUCCESS;
+            }
+
+            match jj_ai::command::apply_split(ctx, plan).await {
+                Ok(commits) => {
+                    eprintln!("Split into {} commit(s):", commits.len().green());
+                    for commit in &commits {
+                        let summary = commit.description().lines().next().unwrap_or("");
+                        let short_id = &commit.change_id().reverse_hex()[..8];
+                        eprintln!("  {}: {}", short_id.cyan(), summary);
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Lint { revision, fix, yes } => {
+            let linted = match jj_ai::command::run_lint(&ctx, &revision).await {
+                Ok(linted) => linted,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            if linted.is_empty() {
+                eprintln!("All descriptions follow the commit standard");
+                return ExitCode::SUCCESS;
+            }
+
+            for item in &linted {
+                let title = item.commit.description().lines().next().unwrap_or("");
+                println!("{} {}", (&item.change_id[..8]).cyan(), title);
+                for violation in &item.violations {
+                    println!("  {}: {}", violation.rule.red(), violation.message);
+                }
+            }
+            if !fix {
+                return ExitCode::FAILURE;
+            }
+
+            let fixes = match jj_ai::command::propose_fixes(&ctx, &linted).await {
+                Ok(fixes) => fixes,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            for fix in &fixes {
+                println!();
+                println!("--- {} ---", &fix.change_id[..8]);
+                println!("{}", fix.description.trim_end());
+                for violation in &fix.remaining {
+                    println!("  {}: {}", violation.rule.yellow(), violation.message);
+                }
+            }
+            println!();
+
+            if !yes && !confirm("Apply these descriptions?") {
+                return ExitCode::FAILURE;
+            }
+            let clean = fixes.iter().all(|fix| fix.remaining.is_empty());
+            match jj_ai::command::apply_fixes(ctx, &fixes) {
+                Ok(()) => {
+                    eprintln!("Rewrote {} description(s)", fixes.len().green());
+                    if clean {
+                        ExitCode::SUCCESS
+                    } else {
+                        ExitCode::FAILURE
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Resolve { revision, dry_run, yes } => {
+            let plan = match jj_ai::command::plan_resolve(&ctx, &revision).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            for file in &plan.files {
+                print!("{}", file.patch);
+            }
+            for (path, reason) in &plan.skipped {
+                eprintln!("Skipped {}: {}", path.yellow(), reason);
+            }
+            if plan.files.is_empty() {
+                eprintln!("No conflicts could be resolved");
+                return ExitCode::FAILURE;
+            }
+
+            if dry_run || (!yes && !confirm("Write these resolutions?")) {
+                return ExitCode::SUCCESS;
+            }
+
+            match jj_ai::command::apply_resolve(ctx, plan).await {
+                Ok(commit) => {
+                    eprintln!(
+                        "Resolved conflicts in {}",
+                        (&commit.change_id().reverse_hex()[..8]).cyan()
+                    );
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::SquashSuggest { revision, apply, yes } => {
+            let plan = match jj_ai::command::plan_squashes(&ctx, &revision).await {
+                Ok(plan) => plan,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            if plan.squashes.is_empty() {
+                eprintln!("No fixup commits found");
+                return ExitCode::SUCCESS;
+            }
+
+            let summary = |commit: &jj_lib::commit::Commit| {
+                let title = commit.description().lines().next().unwrap_or("(no description)");
+                format!("{} {}", (&commit.change_id().reverse_hex()[..8]).cyan(), title)
+            };
+            for squash in &plan.squashes {
+                println!("{}", summary(&squash.source));
+                println!("  into {}", summary(&squash.target));
+                println!("  {}", squash.reason);
+            }
+
+            if !apply || (!yes && !confirm("Squash these commits?")) {
+                return ExitCode::SUCCESS;
+            }
+            match jj_ai::command::apply_squashes(ctx, plan).await {
+                Ok(commits) => {
+                    eprintln!("Squashed into {} commit(s)", commits.len().green());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Review { revision, format } => {
+            match jj_ai::command::run_review(ctx, &revision).await {
+                Ok(reviews) => {
+                    match format {
+                        ReviewFormat::Text => print!("{}", jj_ai::command::render_annotated(&reviews)),
+                        ReviewFormat::Json => println!("{:#}", jj_ai::command::to_json(&reviews)),
+                        ReviewFormat::Sarif => println!("{:#}", jj_ai::command::to_sarif(&reviews)),
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Changelog { revision, format, title } => {
+            match jj_ai::command::run_changelog(ctx, &revision, format, title.as_deref()).await {
+                Ok(changelog) => {
+                    print!("{changelog}");
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Bookmark { revision, dry_run } => {
+            match jj_ai::command::run_bookmark(ctx, &revision, dry_run).await {
+                Ok(result) => {
+                    if result.created {
+                        eprintln!(
+                            "Created bookmark {} at {}",
+                            result.name.green(),
+                            (&result.change_id[..8]).cyan()
+                        );
+                    } else {
+                        println!("{}", result.name);
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Ask { question, revision } => {
+            let mut stdout = std::io::stdout();
+            let mut on_text = |text: &str| {
+                let _ = stdout.write_all(text.as_bytes());
+                let _ = stdout.flush();
+            };
+            match jj_ai::command::run_ask(ctx, &question, &revision, &mut on_text).await {
+                Ok(()) => {
+                    println!();
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Explain { revision, fileset } => {
+            let mut stdout = std::io::stdout();
+            let mut on_text = |text: &str| {
+                // Best effort: a closed stdout shouldn't abort the request.
+                let _ = stdout.write_all(text.as_bytes());
+                let _ = stdout.flush();
+            };
+            match jj_ai::command::run_explain(ctx, &revision, fileset.as_deref(), &mut on_text)
+                .await
+            {
+                Ok(()) => {
+                    println!();
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+        Command::Pr { revision, output } => {
+            match jj_ai::command::run_pr(ctx, &revision).await {
+                Ok(pr) => {
+                    let text = format!("{}\n\n{}\n", pr.title.trim(), pr.body.trim());
+                    match output {
+                        Some(path) => {
+                            if let Err(e) = std::fs::write(&path, text) {
+                                eprintln!("Error: failed to write '{}': {}", path.display(), e);
+                                return ExitCode::FAILURE;
+                            }
+                            eprintln!("Wrote pull request to {}", path.display());
+                        }
+                        None => print!("{text}"),
+                    }
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,113 @@
+use jj_lib::trailer::parse_description_trailers;
+use regex::Regex;
+
+use crate::config::{CommitStandard, TicketPlacement};
+use crate::conventional::parse_header;
+use crate::lint::has_gitmoji_prefix;
+
+/// Trailer key for tickets placed at the end of the message.
+pub const REFS_TRAILER: &str = "Refs";
+
+/// Ticket ids matched by `pattern` in `names`, in order of appearance and
+/// without duplicates.
+pub fn extract_tickets(pattern: &Regex, names: &[String]) -> Vec<String> {
+    let mut tickets: Vec<String> = Vec::new();
+    for name in names {
+        for found in pattern.find_iter(name) {
+            if !tickets.iter().any(|ticket| ticket == found.as_str()) {
+                tickets.push(found.as_str().to_string());
+            }
+        }
+    }
+    tickets
+}
+
+/// Tickets that `description` doesn't mention.
+pub fn missing_tickets<'a>(description: &str, tickets: &'a [String]) -> Vec<&'a str> {
+    tickets
+        .iter()
+        .map(String::as_str)
+        .filter(|ticket| !mentions(description, ticket))
+        .collect()
+}
+
+/// Whether `ticket` appears in `text` as a whole word, so that `PROJ-12`
+/// doesn't count as a mention of `PROJ-1`.
+fn mentions(text: &str, ticket: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    text.match_indices(ticket).any(|(start, _)| {
+        let end = start + ticket.len();
+        !text[..start].chars().next_back().is_some_and(is_word)
+            && !text[end..].chars().next().is_some_and(is_word)
+    })
+}
+
+/// Adds the tickets missing from `description` where `placement` puts them.
+///
+/// A scope only fits a Conventional Commits header, so other subjects get a
+/// `Refs:` trailer instead. A prefix goes after the type or gitmoji that
+/// `standard` starts the subject with.
+pub fn insert_tickets(
+    description: &str,
+    tickets: &[String],
+    placement: TicketPlacement,
+    standard: &CommitStandard,
+) -> String {
+    let missing = missing_tickets(description, tickets);
+    if missing.is_empty() {
+        return description.to_string();
+    }
+    let missing = missing.join(", ");
+    let description = description.trim_end();
+    let (subject, rest) = description.split_once('\n').unwrap_or((description, ""));
+
+    match placement {
+        TicketPlacement::Scope => {
+            if let Some(header) = parse_header(subject) {
+                let scope = match header.scope {
+                    Some(scope) => format!("{scope}, {missing}"),
+                    None => missing,
+                };
+                let breaking = if header.breaking { "!" } else { "" };
+                let subject = format!("{}({scope}){breaking}: {}", header.kind, header.subject);
+                return join_subject(&subject, rest);
+            }
+            insert_tickets(description, tickets, TicketPlacement::Trailer, standard)
+        }
+        TicketPlacement::Prefix => {
+            let subject = match subject_prefix(standard, subject) {
+                Some((prefix, text)) => format!("{prefix}{missing} {text}"),
+                None => format!("{missing}: {subject}"),
+            };
+            join_subject(&subject, rest)
+        }
+        TicketPlacement::Trailer => {
+            // Join an existing trailer paragraph rather than starting another.
+            let separator = if parse_description_trailers(description).is_empty() {
+                "\n\n"
+            } else {
+                "\n"
+            };
+            format!("{description}{separator}{REFS_TRAILER}: {missing}\n")
+        }
+    }
+}
+
+/// Splits the type or gitmoji that `standard` requires off the start of
+/// `subject`.
+fn subject_prefix<'a>(standard: &CommitStandard, subject: &'a str) -> Option<(&'a str, &'a str)> {
+    let text = match standard {
+        CommitStandard::Conventional => parse_header(subject)?.subject,
+        CommitStandard::Gitmoji if has_gitmoji_prefix(subject) => subject.split_once(' ')?.1,
+        _ => return None,
+    };
+    Some(subject.split_at(subject.len() - text.len()))
+}
+
+fn join_subject(subject: &str, rest: &str) -> String {
+    if rest.is_empty() {
+        format!("{subject}\n")
+    } else {
+        format!("{subject}\n{rest}\n")
+    }
+}
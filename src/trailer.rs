@@ -0,0 +1,73 @@
+use anyhow::{bail, Context, Result};
+use jj_lib::trailer::{parse_description_trailers, parse_trailers, Trailer};
+
+/// Trailer key recording the model that wrote a description.
+pub const MODEL_TRAILER: &str = "AI-Model";
+
+/// Splits `description` into its text and the trailers of its last
+/// paragraph.
+pub fn split_trailers(description: &str) -> (&str, Vec<Trailer>) {
+    let description = description.trim_end();
+    let trailers = parse_description_trailers(description);
+    if trailers.is_empty() {
+        return (description, trailers);
+    }
+    let text = description
+        .rsplit_once("\n\n")
+        .map_or("", |(text, _)| text.trim_end());
+    (text, trailers)
+}
+
+/// Parses a `Key: value` trailer line from the config.
+pub fn parse_trailer(line: &str) -> Result<Trailer> {
+    let mut trailers =
+        parse_trailers(line).with_context(|| format!("invalid trailer '{line}'"))?;
+    match trailers.len() {
+        1 => Ok(trailers.remove(0)),
+        _ => bail!("invalid trailer '{line}', expected one `Key: value` line"),
+    }
+}
+
+/// Rewrites `description` with a single trailer block at the end: the
+/// trailers of `original`, then those of `description`, then `added`.
+///
+/// Repeated trailers are kept once. A new `AI-Model` trailer replaces the
+/// old one, since the description is no longer the old model's.
+pub fn merge_trailers(original: &str, description: &str, added: &[Trailer]) -> String {
+    let (_, kept) = split_trailers(original);
+    if kept.is_empty() && added.is_empty() {
+        return description.to_string();
+    }
+    let (text, generated) = split_trailers(description);
+
+    let replaces_model = added.iter().any(|t| t.key.eq_ignore_ascii_case(MODEL_TRAILER));
+    let mut trailers: Vec<Trailer> = Vec::new();
+    for trailer in kept.into_iter().chain(generated).chain(added.iter().cloned()) {
+        if replaces_model
+            && trailer.key.eq_ignore_ascii_case(MODEL_TRAILER)
+            && !added.contains(&trailer)
+        {
+            continue;
+        }
+        if !trailers
+            .iter()
+            .any(|t| t.key.eq_ignore_ascii_case(&trailer.key) && t.value == trailer.value)
+        {
+            trailers.push(trailer);
+        }
+    }
+
+    let mut merged = text.to_string();
+    if !trailers.is_empty() {
+        if !merged.is_empty() {
+            merged.push_str("\n\n");
+        }
+        let lines: Vec<String> = trailers
+            .iter()
+            .map(|t| format!("{}: {}", t.key, t.value))
+            .collect();
+        merged.push_str(&lines.join("\n"));
+    }
+    merged.push('\n');
+    merged
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::run_ask;
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+#[tokio::test]
+async fn packs_matching_commits_by_relevance() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("config.rs", "fn load() {}\n")], "Add config loader");
+    let second = test_repo.commit(
+        &[&first],
+        &[("config.rs", "fn load() { StackedConfig::new() }\n")],
+        "Switch loader to layered config\n\nUser settings now override repo settings.",
+    );
+    let third = test_repo.commit(
+        &[&second],
+        &[("config.rs", "fn load() { StackedConfig::new() }\n"), ("README", "docs\n")],
+        "Add README",
+    );
+
+    let ctx = test_repo.context("");
+    let err = run_ask(ctx, "How is the loader tested?", &third.id().hex(), &mut |_| {})
+        .await
+        .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!("no commits in '{}' mention the question's words", third.id().hex())
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("It happened in [abc].")));
+    ctx.provider = mock.clone();
+    let mut answer = String::new();
+    run_ask(
+        ctx,
+        "When did we switch the loader to StackedConfig?",
+        &format!("::{}", third.id().hex()),
+        &mut |text| answer.push_str(text),
+    )
+    .await
+    .unwrap();
+    assert_eq!(answer, "It happened in [abc].");
+
+    let prompt = &mock.requests()[0][1].content;
+    let second_id = &second.change_id().reverse_hex()[..8];
+    let first_id = &first.change_id().reverse_hex()[..8];
+    assert!(prompt.starts_with(&format!("Commit {second_id} (")));
+    assert!(prompt.contains(&format!("Commit {first_id} (")));
+    assert!(!prompt.contains("Add README"));
+    assert!(prompt.ends_with("Question: When did we switch the loader to StackedConfig?"));
+}
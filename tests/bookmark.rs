@@ -0,0 +1,84 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::run_bookmark;
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+use jj_lib::op_store::RefTarget;
+use jj_lib::ref_name::RefName;
+
+const SUGGESTION: &str = r#"{"type": "feat", "slug": "OAuth Login!"}"#;
+
+#[tokio::test]
+async fn creates_bookmark_from_pattern() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("auth.rs", "fn login() {}\n")], "Add OAuth login");
+
+    let mut ctx = test_repo.context("ai.bookmark-pattern = \"{user}/{type}/{slug}\"");
+    let mock = Arc::new(MockProvider::new(Some(SUGGESTION)));
+    ctx.provider = mock.clone();
+
+    let result = run_bookmark(ctx, &commit.id().hex(), false).await.unwrap();
+    assert_eq!(result.name, "test-user/feat/oauth-login");
+    assert!(result.created);
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("Description:\nAdd OAuth login\n\ndiff --git"));
+
+    let repo = test_repo.reload();
+    assert_eq!(
+        repo.view().get_local_bookmark(RefName::new("test-user/feat/oauth-login")),
+        &RefTarget::normal(commit.id().clone())
+    );
+}
+
+#[tokio::test]
+async fn avoids_existing_names_in_dry_run() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("auth.rs", "fn login() {}\n")], "");
+    test_repo.set_bookmark("feat/oauth-login", &commit);
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(SUGGESTION)));
+
+    let result = run_bookmark(ctx, &commit.id().hex(), true).await.unwrap();
+    assert_eq!(result.name, "feat/oauth-login-2");
+    assert!(!result.created);
+
+    let repo = test_repo.reload();
+    assert!(repo.view().get_local_bookmark(RefName::new("feat/oauth-login-2")).is_absent());
+}
+
+#[tokio::test]
+async fn avoids_remote_bookmark_names() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("auth.rs", "fn login() {}\n")], "");
+    test_repo.set_remote_bookmark("feat/oauth-login", "origin", &commit);
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(SUGGESTION)));
+
+    let result = run_bookmark(ctx, &commit.id().hex(), true).await.unwrap();
+    assert_eq!(result.name, "feat/oauth-login-2");
+}
+
+#[tokio::test]
+async fn empty_slugs_fall_back_to_the_change_id() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("auth.rs", "fn login() {}\n")], "");
+
+    let mut ctx = test_repo.context("ai.bookmark-pattern = \"feat/{slug}\"");
+    ctx.provider = Arc::new(MockProvider::new(Some(r#"{"type": "feat", "slug": "!!!"}"#)));
+    let result = run_bookmark(ctx, &commit.id().hex(), true).await.unwrap();
+    assert_eq!(result.name, format!("feat/{}", &commit.change_id().reverse_hex()[..8]));
+
+    let mut ctx = test_repo.context("ai.bookmark-pattern = \"{type}/{slug}\"");
+    ctx.provider = Arc::new(MockProvider::new(Some(r#"{"type": "", "slug": "login"}"#)));
+    let err = run_bookmark(ctx, &commit.id().hex(), true).await.err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "bookmark pattern '{type}/{slug}' produced an invalid name '/login'"
+    );
+}
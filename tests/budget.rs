@@ -0,0 +1,142 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::budget::{chunk_patch, estimate_tokens, fit_patch, truncate_patch, truncate_text};
+use jj_ai::command::{run_describe, DescribeMode};
+use jj_ai::diff::{CommitPatch, FilePatch, Hunk};
+use jj_ai::llm::MockProvider;
+use jj_lib::diff_presentation::unified::DiffLineType;
+use jj_lib::object_id::ObjectId;
+
+fn added_file(path: &str, lines: usize) -> FilePatch {
+    FilePatch {
+        path: path.to_string(),
+        source: path.to_string(),
+        header: vec![format!("diff --git a/{path} b/{path}")],
+        hunks: vec![Hunk {
+            old_start: 1,
+            old_len: 0,
+            new_start: 1,
+            new_len: lines,
+            lines: (0..lines)
+                .map(|i| (DiffLineType::Added, format!("line {i} of {path}\n")))
+                .collect(),
+        }],
+    }
+}
+
+#[test]
+fn truncation_keeps_small_files_and_all_stats() {
+    let patch = CommitPatch {
+        files: vec![added_file("small.rs", 3), added_file("huge.rs", 2000)],
+    };
+
+    let output = truncate_patch(&patch, 500);
+
+    assert!(estimate_tokens(&output) <= 500);
+    assert!(output.contains("small.rs | +3 -0"));
+    assert!(output.contains("huge.rs | +2000 -0"));
+    assert!(output.contains(&added_file("small.rs", 3).to_string()));
+    assert!(output.contains("more lines of huge.rs omitted"));
+}
+
+#[test]
+fn fitting_keeps_small_patches_whole() {
+    let patch = CommitPatch {
+        files: vec![added_file("small.rs", 3)],
+    };
+    assert_eq!(fit_patch(&patch, 500), patch.to_string());
+    assert_eq!(fit_patch(&patch, 20), truncate_patch(&patch, 20));
+    assert!(estimate_tokens(&fit_patch(&patch, 20)) <= 20);
+}
+
+#[test]
+fn truncation_stays_within_budget_for_many_files() {
+    let patch = CommitPatch {
+        files: (0..500)
+            .map(|i| added_file(&format!("vendor/crate-{i}/src/lib.rs"), 20))
+            .collect(),
+    };
+
+    for budget in [50, 1000, 8000] {
+        let output = truncate_patch(&patch, budget);
+        assert!(estimate_tokens(&output) <= budget, "budget {budget}");
+    }
+    let output = truncate_patch(&patch, 8000);
+    assert!(output.contains("vendor/crate-0/src/lib.rs | +20 -0"));
+    assert!(output.contains(" more files\n"));
+}
+
+#[test]
+fn chunks_stay_within_budget_and_cover_every_file() {
+    let patch = CommitPatch {
+        files: vec![
+            added_file("a.rs", 10),
+            added_file("b.rs", 10),
+            added_file("c.rs", 400),
+        ],
+    };
+
+    let chunks = chunk_patch(&patch, 300);
+
+    assert!(chunks.len() > 1);
+    assert!(chunks.iter().all(|chunk| estimate_tokens(chunk) <= 300));
+    for path in ["a.rs", "b.rs", "c.rs"] {
+        assert!(chunks.iter().any(|chunk| chunk.contains(path)));
+    }
+}
+
+#[tokio::test]
+async fn oversized_diff_is_summarized_in_chunks() {
+    let mut test_repo = TestRepo::init();
+    let big: String = (0..400).map(|i| format!("const VALUE_{i}: u32 = {i};\n")).collect();
+    let commit = test_repo.commit(&[], &[("a.rs", &big), ("b.rs", &big)], "");
+
+    let mut ctx = test_repo.context("ai.token-budget = 100000\nai.token-budgets.mock-model = 2000\nai.oversize = \"chunk\"");
+    let mock = Arc::new(MockProvider::new(Some("Add constants")));
+    ctx.provider = mock.clone();
+
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+    assert_eq!(result.described[0].description, "Add constants");
+
+    let requests = mock.requests();
+    assert!(requests.len() > 2);
+    let final_prompt = &requests.last().unwrap()[1].content;
+    assert!(final_prompt.contains("a.rs | +400 -0"));
+    assert!(final_prompt.contains("Part 1 of"));
+}
+
+#[tokio::test]
+async fn long_chunk_summaries_are_cut_to_the_budget() {
+    let mut test_repo = TestRepo::init();
+    let big: String = (0..400).map(|i| format!("const VALUE_{i}: u32 = {i};\n")).collect();
+    let commit = test_repo.commit(&[], &[("a.rs", &big), ("b.rs", &big)], "");
+
+    let mut ctx = test_repo.context("ai.token-budgets.mock-model = 2000\nai.oversize = \"chunk\"");
+    let summary = "- added a constant\n".repeat(400);
+    let mock = Arc::new(MockProvider::new(Some(&summary)));
+    ctx.provider = mock.clone();
+
+    run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    let requests = mock.requests();
+    let final_prompt = &requests.last().unwrap()[1].content;
+    assert!(estimate_tokens(final_prompt) <= 2000);
+    assert!(final_prompt.contains("Part 1 of"));
+    assert!(final_prompt.contains("- added a constant\n... (truncated)\n"));
+}
+
+#[test]
+fn text_is_cut_at_line_breaks() {
+    assert_eq!(truncate_text("short", 10), "short");
+    let text = "line one\nline two\nline three\n".repeat(10);
+    let cut = truncate_text(&text, 20);
+    assert!(estimate_tokens(&cut) <= 20);
+    assert_eq!(cut, format!("{}line one\nline two\nline three\n... (truncated)\n", &text[..29]));
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{run_changelog, ChangelogFormat};
+use jj_ai::llm::MockProvider;
+use jj_lib::backend::{MillisSinceEpoch, Timestamp};
+use jj_lib::object_id::ObjectId;
+
+#[tokio::test]
+async fn groups_conventional_commits_by_type() {
+    let mut test_repo = TestRepo::init();
+    let feat = test_repo.commit(&[], &[("a.txt", "a\n")], "feat(cli): add a changelog command");
+    let fix = test_repo.commit(&[&feat], &[("a.txt", "b\n")], "fix: handle empty revision ranges");
+    let wip = test_repo.commit(&[&fix], &[("a.txt", "b\n"), ("b.txt", "new\n")], "wip");
+
+    let id = |commit: &jj_lib::commit::Commit| commit.change_id().reverse_hex()[..8].to_string();
+    let response = serde_json::json!({ "entries": [
+        // The model filed this under the wrong section; the type wins.
+        { "section": "Other", "text": "Add `jj ai changelog`", "change_ids": [id(&feat)] },
+        { "section": "Bug Fixes", "text": "Handle empty ranges", "change_ids": [id(&fix), "zzzzzzzz"] },
+        { "section": "Other", "text": "Add b.txt", "change_ids": [id(&wip)] },
+    ]});
+
+    let mut ctx = test_repo.context("ai.standard = \"conventional\"");
+    let mock = Arc::new(MockProvider::new(Some(&response.to_string())));
+    ctx.provider = mock.clone();
+
+    let revset = format!("{}::{}", feat.id().hex(), wip.id().hex());
+    let changelog = run_changelog(ctx, &revset, ChangelogFormat::Markdown, Some("v1.3"))
+        .await
+        .unwrap();
+    assert_eq!(
+        changelog,
+        format!(
+            "## v1.3\n\n\
+             ### Features\n\n- Add `jj ai changelog` ({})\n\n\
+             ### Bug Fixes\n\n- Handle empty ranges ({})\n\n\
+             ### Other\n\n- Add b.txt ({})\n",
+            id(&feat),
+            id(&fix),
+            id(&wip)
+        )
+    );
+
+    // Commits are listed oldest first; only the thin one comes with a diff.
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with(&format!(
+        "Commit {} (type: feat)\nfeat(cli): add a changelog command\n\n",
+        id(&feat)
+    )));
+    assert!(prompt.ends_with(&format!(
+        "Commit {}\nwip\ndiff --git a/b.txt b/b.txt\nnew file mode 100644\n@@ -1,0 +1,1 @@\n+new\n\n",
+        id(&wip)
+    )));
+}
+
+#[tokio::test]
+async fn writes_keep_a_changelog_sections() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("a.txt", "a\n")], "Add the a file to the repo");
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(
+        r#"{"entries": [{"section": "Added", "text": "Add a", "change_ids": []}]}"#,
+    )));
+
+    let changelog = run_changelog(ctx, &commit.id().hex(), ChangelogFormat::KeepAChangelog, None)
+        .await
+        .unwrap();
+    assert_eq!(changelog, "## [Unreleased]\n\n### Added\n\n- Add a\n");
+}
+
+#[tokio::test]
+async fn dates_releases_by_the_newest_commit() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("a.txt", "a\n")], "Add the a file to the repo");
+    let mut tx = test_repo.repo.start_transaction();
+    let mut committer = commit.committer().clone();
+    // 2024-03-01T23:30:00Z, which is already March 2nd at UTC+2.
+    committer.timestamp = Timestamp {
+        timestamp: MillisSinceEpoch(1_709_335_800_000),
+        tz_offset: 120,
+    };
+    let commit = tx
+        .repo_mut()
+        .rewrite_commit(&commit)
+        .set_committer(committer)
+        .write()
+        .unwrap();
+    tx.repo_mut().rebase_descendants().unwrap();
+    test_repo.repo = tx.commit("test committer").unwrap();
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(
+        r#"{"entries": [{"section": "Added", "text": "Add a", "change_ids": []}]}"#,
+    )));
+
+    let changelog = run_changelog(
+        ctx,
+        &commit.id().hex(),
+        ChangelogFormat::KeepAChangelog,
+        Some("1.0.0"),
+    )
+    .await
+    .unwrap();
+    assert_eq!(changelog, "## [1.0.0] - 2024-03-02\n\n### Added\n\n- Add a\n");
+}
@@ -0,0 +1,165 @@
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use jj_ai::command::CommandContext;
+use jj_lib::backend::{CommitId, CopyId, TreeValue};
+use jj_lib::commit::Commit;
+use jj_lib::config::{ConfigLayer, ConfigSource, StackedConfig};
+use jj_lib::merge::{Merge, MergedTreeValue};
+use jj_lib::merged_tree::{MergedTree, MergedTreeBuilder};
+use jj_lib::op_store::{RefTarget, RemoteRef, RemoteRefState};
+use jj_lib::ref_name::{RefName, RemoteName, RemoteRefSymbol};
+use jj_lib::repo::{ReadonlyRepo, Repo};
+use jj_lib::repo_path::RepoPathBuf;
+use jj_lib::settings::UserSettings;
+use jj_lib::workspace::Workspace;
+use pollster::FutureExt;
+use tempfile::TempDir;
+
+/// A throwaway jj repository backed by an internal git store.
+pub struct TestRepo {
+    _dir: TempDir,
+    pub root: PathBuf,
+    pub repo: Arc<ReadonlyRepo>,
+}
+
+impl TestRepo {
+    pub fn init() -> Self {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let settings = UserSettings::from_config(base_config("")).unwrap();
+        let (_, repo) = Workspace::init_internal_git(&settings, &root).unwrap();
+        Self {
+            _dir: dir,
+            root,
+            repo,
+        }
+    }
+
+    /// Writes a tree containing exactly `files` (path, content).
+    pub fn tree(&self, files: &[(&str, &str)]) -> MergedTree {
+        let entries = files
+            .iter()
+            .map(|(path, content)| (*path, self.file_value(path, content.as_bytes(), false)))
+            .collect();
+        self.tree_from(entries)
+    }
+
+    /// Writes a tree containing exactly the given entries.
+    pub fn tree_from(&self, entries: Vec<(&str, MergedTreeValue)>) -> MergedTree {
+        let mut builder = MergedTreeBuilder::new(self.repo.store().empty_merged_tree());
+        for (path, value) in entries {
+            builder.set_or_remove(RepoPathBuf::from_internal_string(path).unwrap(), value);
+        }
+        builder.write_tree().unwrap()
+    }
+
+    pub fn file_value(&self, path: &str, content: &[u8], executable: bool) -> MergedTreeValue {
+        let path = RepoPathBuf::from_internal_string(path).unwrap();
+        let id = self
+            .repo
+            .store()
+            .write_file(&path, &mut &content[..])
+            .block_on()
+            .unwrap();
+        Merge::normal(TreeValue::File {
+            id,
+            executable,
+            copy_id: CopyId::placeholder(),
+        })
+    }
+
+    pub fn symlink_value(&self, path: &str, target: &str) -> MergedTreeValue {
+        let path = RepoPathBuf::from_internal_string(path).unwrap();
+        let id = self
+            .repo
+            .store()
+            .write_symlink(&path, target)
+            .block_on()
+            .unwrap();
+        Merge::normal(TreeValue::Symlink(id))
+    }
+
+    /// Creates a commit on top of `parents` (the root commit if empty).
+    pub fn commit(&mut self, parents: &[&Commit], files: &[(&str, &str)], description: &str) -> Commit {
+        let tree = self.tree(files);
+        self.commit_tree(parents, tree, description)
+    }
+
+    pub fn commit_tree(&mut self, parents: &[&Commit], tree: MergedTree, description: &str) -> Commit {
+        let parent_ids: Vec<CommitId> = if parents.is_empty() {
+            vec![self.repo.store().root_commit_id().clone()]
+        } else {
+            parents.iter().map(|c| c.id().clone()).collect()
+        };
+        let mut tx = self.repo.start_transaction();
+        let commit = tx
+            .repo_mut()
+            .new_commit(parent_ids, tree)
+            .set_description(description)
+            .write()
+            .unwrap();
+        self.repo = tx.commit("test commit").unwrap();
+        commit
+    }
+
+    pub fn set_bookmark(&mut self, name: &str, commit: &Commit) {
+        let mut tx = self.repo.start_transaction();
+        tx.repo_mut().set_local_bookmark_target(
+            RefName::new(name),
+            RefTarget::normal(commit.id().clone()),
+        );
+        self.repo = tx.commit("test bookmark").unwrap();
+    }
+
+    /// Points the bookmark `name` on `remote` at `commit`, without a local
+    /// bookmark.
+    pub fn set_remote_bookmark(&mut self, name: &str, remote: &str, commit: &Commit) {
+        let mut tx = self.repo.start_transaction();
+        tx.repo_mut().set_remote_bookmark(
+            RemoteRefSymbol {
+                name: RefName::new(name),
+                remote: RemoteName::new(remote),
+            },
+            RemoteRef {
+                target: RefTarget::normal(commit.id().clone()),
+                state: RemoteRefState::New,
+            },
+        );
+        self.repo = tx.commit("test remote bookmark").unwrap();
+    }
+
+    /// Opens a command context for this repo with extra TOML settings.
+    pub fn context(&self, settings: &str) -> CommandContext {
+        CommandContext::load(&self.root, base_config(settings)).unwrap()
+    }
+
+    /// Reloads the repo at the latest operation.
+    pub fn reload(&mut self) -> Arc<ReadonlyRepo> {
+        self.repo = self.repo.reload_at_head().unwrap();
+        self.repo.clone()
+    }
+
+    /// Looks up the current version of `commit` by change id.
+    pub fn current(&mut self, commit: &Commit) -> Commit {
+        let repo = self.reload();
+        let ids = repo.resolve_change_id(commit.change_id()).unwrap().unwrap();
+        let (_, id) = ids.visible_with_offsets().next().unwrap();
+        repo.store().get_commit(id).unwrap()
+    }
+}
+
+pub fn base_config(settings: &str) -> StackedConfig {
+    let mut config = StackedConfig::with_defaults();
+    let mut layer = ConfigLayer::empty(ConfigSource::User);
+    layer.set_value("user.name", "Test User").unwrap();
+    layer.set_value("user.email", "test.user@example.com").unwrap();
+    layer.set_value("ai.provider", "mock").unwrap();
+    layer.set_value("ai.model", "mock-model").unwrap();
+    layer.set_value("ai.mock.response", "Mock description").unwrap();
+    config.add_layer(layer);
+    config.add_layer(ConfigLayer::parse(ConfigSource::Repo, settings).unwrap());
+    config
+}
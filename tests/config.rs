@@ -0,0 +1,25 @@
+mod common;
+
+use common::base_config;
+use jj_ai::config::JjaiConfig;
+
+#[test]
+fn missing_keys_fall_back_to_defaults() {
+    let cfg = JjaiConfig::try_from(&base_config("ai.api-key = \"from-config\"")).unwrap();
+    assert_eq!(cfg.concurrency(), 4);
+    assert_eq!(cfg.max_retries(), 3);
+    assert!(cfg.stack_context());
+    assert_eq!(cfg.api_key(), Some("from-config"));
+}
+
+#[test]
+fn invalid_values_are_reported() {
+    for (settings, name) in [
+        ("ai.concurrency = \"four\"", "ai.concurrency"),
+        ("ai.max-retries = -1", "ai.max-retries"),
+        ("ai.stack-context = \"yes\"", "ai.stack-context"),
+    ] {
+        let err = JjaiConfig::try_from(&base_config(settings)).err().unwrap();
+        assert_eq!(err.to_string(), format!("Invalid type or value for {name}"));
+    }
+}
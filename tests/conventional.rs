@@ -0,0 +1,26 @@
+use jj_ai::conventional::{parse_header, section_for, Header};
+
+#[test]
+fn parses_conventional_headers() {
+    assert_eq!(
+        parse_header("feat(cli)!: add split"),
+        Some(Header {
+            kind: "feat",
+            scope: Some("cli"),
+            breaking: true,
+            subject: "add split",
+        })
+    );
+    assert_eq!(parse_header("fix: typo").unwrap().scope, None);
+
+    assert_eq!(parse_header("Add split"), None);
+    assert_eq!(parse_header("feat(): add split"), None);
+    assert_eq!(parse_header("Feat: add split"), None);
+    assert_eq!(parse_header("feat: "), None);
+}
+
+#[test]
+fn maps_types_to_sections() {
+    assert_eq!(section_for("feat"), Some("Features"));
+    assert_eq!(section_for("unknown"), None);
+}
@@ -0,0 +1,462 @@
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use common::TestRepo;
+use jj_ai::budget::estimate_tokens;
+use jj_ai::command::{run_describe, DescribeMode};
+use jj_ai::diff::render_commit_patch;
+use jj_ai::llm::{diff_hash, ChatMessage, MockProvider, Provider, ThrottledProvider};
+use jj_lib::commit::Commit;
+use jj_lib::merge::Merge;
+use jj_lib::merged_tree::MergedTree;
+use jj_lib::object_id::ObjectId;
+use jj_lib::rewrite::merge_commit_trees;
+
+#[tokio::test]
+async fn describes_commit_and_records_operation() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("src/main.rs", "fn main() {}\n")], "");
+
+    let ctx = test_repo.context("");
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    assert!(result.applied);
+    assert_eq!(result.described.len(), 1);
+    assert_eq!(result.described[0].change_id, commit.change_id().reverse_hex());
+
+    let rewritten = test_repo.current(&commit);
+    assert_eq!(rewritten.description(), "Mock description");
+    assert_eq!(
+        test_repo.repo.operation().metadata().description,
+        "ai describe"
+    );
+}
+
+#[tokio::test]
+async fn skips_described_commits_unless_overwriting() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("README.md", "hello\n")], "Existing message");
+
+    let ctx = test_repo.context("");
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+    assert!(result.described.is_empty());
+    assert_eq!(result.skipped_existing, 1);
+    assert_eq!(test_repo.current(&commit).description(), "Existing message");
+
+    let ctx = test_repo.context("");
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Overwrite, false)
+        .await
+        .unwrap();
+    assert_eq!(result.described.len(), 1);
+    assert_eq!(test_repo.current(&commit).description(), "Mock description");
+}
+
+#[tokio::test]
+async fn dry_run_leaves_repo_untouched() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("a.txt", "a\n")], "");
+    let op_before = test_repo.repo.op_id().clone();
+
+    let ctx = test_repo.context("");
+    let result = run_describe(ctx, &commit.id().hex(), true, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    assert!(!result.applied);
+    assert_eq!(result.described[0].description, "Mock description");
+    assert_eq!(test_repo.reload().op_id(), &op_before);
+}
+
+#[tokio::test]
+async fn scripted_responses_describe_a_stack() {
+    let mut test_repo = TestRepo::init();
+    let parent = test_repo.commit(&[], &[("lib.rs", "pub fn a() {}\n")], "");
+    let child = test_repo.commit(
+        &[&parent],
+        &[("lib.rs", "pub fn a() {}\npub fn b() {}\n")],
+        "",
+    );
+    test_repo.set_bookmark("feature", &child);
+
+    let mut ctx = test_repo.context("");
+    let parent_diff = render_commit_patch(ctx.repo.as_ref(), &parent, ctx.cfg.ignore())
+        .await
+        .unwrap();
+    let child_diff = render_commit_patch(ctx.repo.as_ref(), &child, ctx.cfg.ignore())
+        .await
+        .unwrap();
+
+    let responses_path = test_repo.root.join("responses.json");
+    // Responses are keyed by the diff alone, whatever notes precede it.
+    let responses = serde_json::json!({
+        diff_hash(&parent_diff): "Add function a",
+        diff_hash(&child_diff): { "message": "Add function b" },
+    });
+    std::fs::write(&responses_path, responses.to_string()).unwrap();
+
+    let mock = Arc::new(MockProvider::new(None).with_responses_file(&responses_path).unwrap());
+    ctx.provider = mock.clone();
+
+    let revset = format!("{} | {}", parent.id().hex(), child.id().hex());
+    let result = run_describe(ctx, &revset, false, DescribeMode::Fill, false).await.unwrap();
+    assert_eq!(result.described.len(), 2);
+    assert_eq!(mock.requests().len(), 2);
+
+    // The child is described after its parent, with the parent's new
+    // description as context; both belong to the bookmark on top.
+    let bookmark_note = "This commit is part of the bookmark `feature`.\n";
+    assert_eq!(
+        mock.requests()[0][1].content,
+        format!("{bookmark_note}\n{parent_diff}")
+    );
+    assert_eq!(
+        mock.requests()[1][1].content,
+        format!(
+            "{bookmark_note}It is part of a stack of commits. Its parents are described as:\n\
+             ---\nAdd function a\n---\n\
+             Explain this commit's role in the series, consistent with the parent messages \
+             but without repeating them.\n\n{child_diff}"
+        )
+    );
+
+    let parent = test_repo.current(&parent);
+    let child = test_repo.current(&child);
+    assert_eq!(parent.description(), "Add function a");
+    assert_eq!(child.description(), "Add function b");
+    assert_eq!(child.parent_ids(), &[parent.id().clone()]);
+}
+
+#[tokio::test]
+async fn single_commits_see_their_described_parents() {
+    let mut test_repo = TestRepo::init();
+    let parent = test_repo.commit(&[], &[("lib.rs", "pub fn a() {}\n")], "Add function a\n");
+    let child = test_repo.commit(
+        &[&parent],
+        &[("lib.rs", "pub fn a() {}\npub fn b() {}\n")],
+        "",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("Add function b")));
+    ctx.provider = mock.clone();
+    run_describe(ctx, &child.id().hex(), true, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    assert!(mock.requests()[0][1].content.starts_with(
+        "It is part of a stack of commits. Its parents are described as:\n\
+         ---\nAdd function a\n---\n"
+    ));
+}
+
+#[tokio::test]
+async fn mock_without_response_reports_diff_hash() {
+    let test_repo = {
+        let mut test_repo = TestRepo::init();
+        test_repo.commit(&[], &[("x.txt", "x\n")], "");
+        test_repo
+    };
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(None));
+
+    let err = run_describe(ctx, "all() ~ root() ~ @", true, DescribeMode::Fill, false)
+        .await
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("no mock response for diff hash"));
+}
+
+/// Creates a commit whose tree has a content conflict in `file.txt`.
+fn conflicted_commit(test_repo: &mut TestRepo) -> jj_lib::commit::Commit {
+    let base = test_repo.commit(&[], &[("file.txt", "base\n")], "base");
+    let side1 = test_repo.commit(&[&base], &[("file.txt", "one\n")], "side 1");
+    let side2_tree = test_repo.tree(&[("file.txt", "two\n")]);
+    let conflicted_tree = MergedTree::merge_no_resolve(Merge::from_vec(vec![
+        (side1.tree(), "side 1".to_string()),
+        (base.tree(), "base".to_string()),
+        (side2_tree, "side 2".to_string()),
+    ]));
+    test_repo.commit_tree(&[&side1], conflicted_tree, "")
+}
+
+#[tokio::test]
+async fn conflicted_commits_are_skipped_when_configured() {
+    let mut test_repo = TestRepo::init();
+    let commit = conflicted_commit(&mut test_repo);
+
+    let ctx = test_repo.context("ai.conflicts = \"skip\"");
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    assert!(result.described.is_empty());
+    assert_eq!(result.skipped_conflicted, 1);
+}
+
+#[tokio::test]
+async fn conflicts_are_materialized_and_mentioned() {
+    let mut test_repo = TestRepo::init();
+    let commit = conflicted_commit(&mut test_repo);
+
+    let mut ctx = test_repo.context("ai.conflicts = \"mention\"");
+    let mock = Arc::new(MockProvider::new(Some("Merge sides (conflicted)")));
+    ctx.provider = mock.clone();
+
+    let result = run_describe(ctx, &commit.id().hex(), true, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+    assert_eq!(result.described[0].conflicted_paths, vec!["file.txt"]);
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.contains("without repeating them.\n\nThese paths have unresolved conflicts"));
+    assert!(prompt.contains("- file.txt\nMention the unresolved conflicts"));
+    assert!(prompt.contains("+<<<<<<<"));
+}
+
+#[tokio::test]
+async fn merges_are_diffed_against_merged_parents() {
+    let mut test_repo = TestRepo::init();
+    let base = test_repo.commit(&[], &[("a.txt", "a\n"), ("b.txt", "b\n")], "base");
+    let left = test_repo.commit(&[&base], &[("a.txt", "left\n"), ("b.txt", "b\n")], "Change a");
+    let right = test_repo.commit(&[&base], &[("a.txt", "a\n"), ("b.txt", "right\n")], "Change b");
+    test_repo.set_bookmark("feature", &right);
+    let merged_tree = merge_commit_trees(test_repo.repo.as_ref(), &[left.clone(), right.clone()])
+        .await
+        .unwrap();
+    let merge = test_repo.commit_tree(&[&left, &right], merged_tree, "");
+
+    assert_eq!(render_commit_patch(test_repo.repo.as_ref(), &merge, &[]).await.unwrap(), "");
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("Merge feature")));
+    ctx.provider = mock.clone();
+
+    let result = run_describe(ctx, &merge.id().hex(), true, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+    assert_eq!(result.described[0].description, "Merge feature");
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("This is a merge commit."));
+    let left_id = &left.change_id().reverse_hex()[..8];
+    let right_id = &right.change_id().reverse_hex()[..8];
+    assert!(prompt.contains(&format!("- {left_id}: Change a\n")));
+    assert!(prompt.contains(&format!("- {right_id} (feature): Change b\n")));
+    assert!(prompt.ends_with("(no changes beyond merging the parents)\n"));
+}
+
+#[tokio::test]
+async fn follows_project_prompt_and_history() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "a\n")], "Add parser\n");
+    let second = test_repo.commit(&[&first], &[("a.txt", "b\n")], "wip.\n");
+    let third = test_repo.commit(&[&second], &[("a.txt", "c\n")], "Fix parser crash\n");
+    let commit = test_repo.commit(&[&third], &[("a.txt", "d\n")], "");
+    std::fs::create_dir_all(test_repo.root.join(".jj-ai")).unwrap();
+    std::fs::write(
+        test_repo.root.join(".jj-ai/prompt.md"),
+        "Name the module in the subject.\n",
+    )
+    .unwrap();
+
+    let mut ctx = test_repo.context(&format!(
+        "ai.standard = \"generic\"\n\
+         ai.history-examples = 2\n\
+         revset-aliases.\"trunk()\" = \"{}\"",
+        third.id().hex()
+    ));
+    let mock = Arc::new(MockProvider::new(Some("Change a")));
+    ctx.provider = mock.clone();
+    run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    let system = &mock.requests()[0][0].content;
+    assert!(system.contains("\n\nProject guidelines:\nName the module in the subject."));
+    assert!(system.ends_with(
+        "Recent commit messages in this project, match their style:\n\
+         ---\nFix parser crash\n---\nAdd parser\n---"
+    ));
+}
+
+#[tokio::test]
+async fn project_guidelines_count_against_the_budget() {
+    let mut test_repo = TestRepo::init();
+    let big: String = (0..400).map(|i| format!("const VALUE_{i}: u32 = {i};\n")).collect();
+    let commit = test_repo.commit(&[], &[("a.rs", &big)], "");
+    std::fs::create_dir_all(test_repo.root.join(".jj-ai")).unwrap();
+    std::fs::write(
+        test_repo.root.join(".jj-ai/prompt.md"),
+        "Name the module in the subject.\n".repeat(100),
+    )
+    .unwrap();
+
+    let mut ctx = test_repo.context("ai.token-budget = 2000");
+    let mock = Arc::new(MockProvider::new(Some("Add constants")));
+    ctx.provider = mock.clone();
+    run_describe(ctx, &commit.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    let request = &mock.requests()[0];
+    let tokens = estimate_tokens(&request[0].content) + estimate_tokens(&request[1].content);
+    assert!(tokens <= 2000, "{tokens} tokens");
+    assert!(request[1].content.contains("more lines of a.rs omitted"));
+}
+
+#[tokio::test]
+async fn improves_existing_descriptions() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(
+        &[],
+        &[("login.rs", "fn login() {}\n")],
+        "added login.\n\nRefs: PROJ-1\n",
+    );
+
+    let mut ctx = test_repo.context("ai.standard = \"generic\"");
+    let mock = Arc::new(MockProvider::new(Some("Add login")));
+    ctx.provider = mock.clone();
+    let result = run_describe(ctx, &commit.id().hex(), false, DescribeMode::Improve, false)
+        .await
+        .unwrap();
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with(
+        "Current message:\nadded login.\n\nProblems to fix:\n- subject ends with a period\n\
+         - subject does not start with a capital letter\n\n"
+    ));
+    assert!(prompt.contains("+fn login() {}\n"));
+    assert_eq!(
+        result.described[0].previous_description.as_deref(),
+        Some("added login.\n\nRefs: PROJ-1\n")
+    );
+    assert_eq!(test_repo.current(&commit).description(), "Add login\n\nRefs: PROJ-1\n");
+}
+
+#[tokio::test]
+async fn improving_keeps_the_commit_context() {
+    let mut test_repo = TestRepo::init();
+    let parent = test_repo.commit(&[], &[("a.txt", "a\n")], "Add a");
+    let commit = test_repo.commit(&[&parent], &[("a.txt", "a\n"), ("b.txt", "b\n")], "added b");
+    test_repo.set_bookmark("feat/PROJ-7-b", &commit);
+
+    let mut ctx = test_repo.context(
+        "ai.standard = \"generic\"\n\
+         ai.ticket-pattern = '[A-Z]+-\\d+'",
+    );
+    let mock = Arc::new(MockProvider::new(Some("Add b\n\nRefs: PROJ-7")));
+    ctx.provider = mock.clone();
+    run_describe(ctx, &commit.id().hex(), true, DescribeMode::Improve, false)
+        .await
+        .unwrap();
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("This commit is part of the bookmark `feat/PROJ-7-b`.\n"));
+    assert!(prompt.contains("Its parents are described as:\n---\nAdd a\n---\n"));
+    assert!(prompt.contains("Reference PROJ-7 in a `Refs: PROJ-7` trailer"));
+    assert!(prompt.contains("\n\nCurrent message:\nadded b\n\n"));
+}
+
+/// Names the first file in the diff and tracks how many requests are in
+/// flight at once.
+#[derive(Default)]
+struct SlowProvider {
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+#[async_trait]
+impl Provider for SlowProvider {
+    async fn chat(&self, _model: &str, messages: &[ChatMessage]) -> anyhow::Result<String> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let prompt = &messages.last().unwrap().content;
+        let path = prompt
+            .split_once("diff --git a/")
+            .and_then(|(_, rest)| rest.split_once(' '))
+            .map_or("nothing", |(path, _)| path);
+        Ok(format!("Add {path}"))
+    }
+
+    async fn structured(
+        &self,
+        model: &str,
+        messages: &[ChatMessage],
+        _format: &orpheus::prelude::Format,
+    ) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::json!({ "message": self.chat(model, messages).await? }))
+    }
+}
+
+#[tokio::test]
+async fn stack_context_trades_concurrency_for_parent_descriptions() {
+    let mut test_repo = TestRepo::init();
+    let mut files = Vec::new();
+    let mut stack: Vec<Commit> = Vec::new();
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        files.push((name, "x\n"));
+        let parents: Vec<_> = stack.last().into_iter().collect();
+        stack.push(test_repo.commit(&parents, &files, ""));
+    }
+    let revset = format!("{}::", stack[0].id().hex());
+
+    for (stack_context, max_in_flight) in [(true, 1), (false, 4)] {
+        let mut ctx = test_repo.context(&format!(
+            "ai.concurrency = 4\nai.stack-context = {stack_context}"
+        ));
+        let slow = Arc::new(SlowProvider::default());
+        ctx.provider = Arc::new(ThrottledProvider::new(slow.clone(), ctx.cfg.concurrency(), 0));
+        let result = run_describe(ctx, &revset, true, DescribeMode::Fill, false)
+            .await
+            .unwrap();
+
+        assert_eq!(result.described.len(), 4);
+        assert_eq!(slow.max_in_flight.load(Ordering::SeqCst), max_in_flight);
+    }
+}
+
+#[tokio::test]
+async fn describes_a_stack_concurrently() {
+    let mut test_repo = TestRepo::init();
+    let base = test_repo.commit(&[], &[("base.txt", "base\n")], "");
+    let mut tops = Vec::new();
+    for name in ["a.txt", "b.txt", "c.txt", "d.txt"] {
+        tops.push(test_repo.commit(&[&base], &[("base.txt", "base\n"), (name, "x\n")], ""));
+    }
+
+    let mut ctx = test_repo.context("ai.concurrency = 2");
+    let slow = Arc::new(SlowProvider::default());
+    ctx.provider = Arc::new(ThrottledProvider::new(
+        slow.clone(),
+        ctx.cfg.concurrency(),
+        0,
+    ));
+    let revset = format!("{}::", base.id().hex());
+    let result = run_describe(ctx, &revset, false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    // Children before parents, like the revset.
+    let described: Vec<_> = result.described.iter().map(|d| d.commit_id.clone()).collect();
+    let expected: Vec<_> = tops.iter().rev().chain([&base]).map(|c| c.id().hex()).collect();
+    assert_eq!(described, expected);
+    assert_eq!(slow.max_in_flight.load(Ordering::SeqCst), 2);
+
+    assert_eq!(test_repo.current(&base).description(), "Add base.txt");
+    for (commit, name) in tops.iter().zip(["a.txt", "b.txt", "c.txt", "d.txt"]) {
+        let commit = test_repo.current(commit);
+        assert_eq!(commit.description(), format!("Add {name}"));
+        assert_eq!(commit.parent_ids(), &[test_repo.current(&base).id().clone()]);
+    }
+}
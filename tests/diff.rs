@@ -0,0 +1,80 @@
+mod common;
+
+use common::TestRepo;
+use jj_ai::diff::render_commit_patch;
+use jj_lib::backend::TreeValue;
+use jj_lib::merge::Merge;
+use jj_lib::object_id::ObjectId;
+use jj_lib::repo::Repo;
+
+fn numbered_lines(count: usize) -> String {
+    (0..count).map(|i| format!("let value_{i} = {i};\n")).collect()
+}
+
+#[tokio::test]
+async fn renamed_file_shows_only_content_delta() {
+    let mut test_repo = TestRepo::init();
+    let original = numbered_lines(20);
+    let parent = test_repo.commit(&[], &[("src/old.rs", &original)], "add old");
+    let moved = original.replace("let value_7 = 7;", "let value_7 = 70;");
+    let child = test_repo.commit(&[&parent], &[("src/new.rs", &moved)], "");
+
+    let diff = render_commit_patch(test_repo.repo.as_ref(), &child, &[])
+        .await
+        .unwrap();
+
+    assert!(diff.starts_with("diff --git a/src/old.rs b/src/new.rs\nrename from src/old.rs\nrename to src/new.rs\n"));
+    assert!(diff.contains("-let value_7 = 7;\n+let value_7 = 70;\n"));
+    assert!(!diff.contains("+let value_0 = 0;"));
+    assert_eq!(diff.matches("diff --git").count(), 1);
+}
+
+#[tokio::test]
+async fn copied_file_keeps_source() {
+    let mut test_repo = TestRepo::init();
+    let original = numbered_lines(20);
+    let parent = test_repo.commit(&[], &[("a.rs", &original)], "add a");
+    // Git only considers modified files as copy sources.
+    let modified = format!("{original}let extra = 0;\n");
+    let child = test_repo.commit(&[&parent], &[("a.rs", &modified), ("b.rs", &original)], "");
+
+    let diff = render_commit_patch(test_repo.repo.as_ref(), &child, &[])
+        .await
+        .unwrap();
+
+    assert!(diff.contains("diff --git a/a.rs b/b.rs\ncopy from a.rs\ncopy to b.rs\n"));
+    assert_eq!(diff.matches("+let value_0 = 0;").count(), 0);
+}
+
+#[tokio::test]
+async fn special_entries_use_git_style_headers() {
+    let mut test_repo = TestRepo::init();
+    let parent_tree = test_repo.tree_from(vec![
+        ("run.sh", test_repo.file_value("run.sh", b"echo hi\n", false)),
+        ("logo.png", test_repo.file_value("logo.png", b"\x89PNG\0\x01", false)),
+        ("current", test_repo.symlink_value("current", "v1")),
+    ]);
+    let parent = test_repo.commit_tree(&[], parent_tree, "parent");
+
+    let submodule_id = test_repo.repo.store().root_commit_id().clone();
+    let child_tree = test_repo.tree_from(vec![
+        ("run.sh", test_repo.file_value("run.sh", b"echo hi\n", true)),
+        ("logo.png", test_repo.file_value("logo.png", b"\x89PNG\0\x02", false)),
+        ("current", test_repo.symlink_value("current", "v2")),
+        ("vendor/lib", Merge::normal(TreeValue::GitSubmodule(submodule_id.clone()))),
+    ]);
+    let child = test_repo.commit_tree(&[&parent], child_tree, "");
+
+    let diff = render_commit_patch(test_repo.repo.as_ref(), &child, &[])
+        .await
+        .unwrap();
+
+    assert!(diff.contains("diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\n"));
+    assert!(diff.contains("Binary files a/logo.png and b/logo.png differ\n"));
+    assert!(!diff.contains("PNG"));
+    assert!(diff.contains("-v1\n\\ No newline at end of file\n+v2\n\\ No newline at end of file\n"));
+    assert!(diff.contains(&format!(
+        "diff --git a/vendor/lib b/vendor/lib\nnew file mode 160000\n@@ -1,0 +1,1 @@\n+Subproject commit {}\n",
+        submodule_id.hex()
+    )));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::run_explain;
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+#[tokio::test]
+async fn streams_explanation_with_wide_context() {
+    let mut test_repo = TestRepo::init();
+    let lines: Vec<String> = (1..=30).map(|i| format!("line {i}\n")).collect();
+    let mut changed = lines.clone();
+    changed[14] = "changed\n".to_string();
+    let base = test_repo.commit(&[], &[("f.txt", &lines.concat())], "base");
+    let commit = test_repo.commit(&[&base], &[("f.txt", &changed.concat())], "Change line 15");
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("It changes a line.")));
+    ctx.provider = mock.clone();
+
+    let mut streamed = String::new();
+    run_explain(ctx, &commit.id().hex(), None, &mut |text| streamed.push_str(text))
+        .await
+        .unwrap();
+    assert_eq!(streamed, "It changes a line.");
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("Description:\nChange line 15\n\ndiff --git a/f.txt b/f.txt\n"));
+    assert!(prompt.contains("@@ -5,21 +5,21 @@\n"));
+}
+
+#[tokio::test]
+async fn focuses_on_fileset() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(
+        &[],
+        &[("src/lib.rs", "fn a() {}\n"), ("README", "docs\n")],
+        "",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("Adds a.")));
+    ctx.provider = mock.clone();
+
+    run_explain(ctx, &commit.id().hex(), Some("root:src"), &mut |_| {})
+        .await
+        .unwrap();
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("Description: (none)\n\ndiff --git a/src/lib.rs"));
+    assert!(!prompt.contains("README"));
+
+    let ctx = test_repo.context("");
+    let err = run_explain(ctx, &commit.id().hex(), Some("root:docs"), &mut |_| {})
+        .await
+        .unwrap_err();
+    assert_eq!(err.to_string(), "commit has no changes in 'root:docs'");
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{apply_fixes, propose_fixes, run_lint, CommandContext};
+use jj_ai::config::CommitStandard;
+use jj_ai::lint::lint_description;
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+fn rules(standard: &CommitStandard, description: &str) -> Vec<String> {
+    lint_description(standard, description)
+        .into_iter()
+        .map(|violation| violation.rule)
+        .collect()
+}
+
+#[test]
+fn checks_generic_rules() {
+    let generic = CommitStandard::Generic;
+    assert!(rules(&generic, "Add login\n\nExplain why the login is needed.\n").is_empty());
+    assert_eq!(rules(&generic, ""), ["empty"]);
+    assert_eq!(
+        rules(&generic, "add a login form that is far too long for a subject."),
+        ["subject-length", "subject-period", "subject-case"]
+    );
+
+    let long_line = "word ".repeat(16);
+    assert_eq!(
+        rules(&generic, &format!("Add login\nbody\n{long_line}\nhttps://example.com/{}", "a".repeat(80))),
+        ["body-separator", "body-length"]
+    );
+    assert_eq!(
+        lint_description(&generic, &format!("Add login\n\n{long_line}\nmore"))[0].message,
+        "line 3 is 80 characters, over 72"
+    );
+    let trailers = "Co-authored-by: Someone With A Long Name <someone.with.a.long.name@example.com>";
+    assert!(rules(&generic, &format!("Add login\n\nBody.\n\n{trailers}\n")).is_empty());
+}
+
+#[test]
+fn checks_conventional_and_gitmoji_prefixes() {
+    let conventional = CommitStandard::Conventional;
+    assert!(rules(&conventional, "feat(auth): add login").is_empty());
+    assert_eq!(rules(&conventional, "Add login"), ["conventional-header"]);
+    assert_eq!(
+        lint_description(&conventional, "feature: add login")[0].message,
+        "unknown type 'feature', expected one of: feat, fix, perf, refactor, docs, style, \
+         test, build, ci, chore, revert"
+    );
+
+    let gitmoji = CommitStandard::Gitmoji;
+    assert!(rules(&gitmoji, "✨ add login").is_empty());
+    assert!(rules(&gitmoji, "♻️ simplify login").is_empty());
+    assert!(rules(&gitmoji, ":bug: fix login").is_empty());
+    assert_eq!(rules(&gitmoji, "Add login"), ["gitmoji-prefix"]);
+    assert_eq!(rules(&gitmoji, "🙂 add login"), ["gitmoji-prefix"]);
+}
+
+#[tokio::test]
+async fn fixes_failing_commits_in_a_stack() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "a\n")], "added a.");
+    let second = test_repo.commit(&[&first], &[("a.txt", "a\n"), ("b.txt", "b\n")], "Add b");
+    let third = test_repo.commit(
+        &[&second],
+        &[("a.txt", "a\n"), ("b.txt", "b\n"), ("c.txt", "c\n")],
+        "fixed c",
+    );
+
+    let revision = format!("{}::{}", first.id().hex(), third.id().hex());
+    let mut ctx = test_repo.context("ai.standard = \"generic\"");
+    let mock = Arc::new(MockProvider::new(Some("Add a file")));
+    ctx.provider = mock.clone();
+
+    let linted = run_lint(&ctx, &revision).await.unwrap();
+    let ids: Vec<_> = linted.iter().map(|item| item.commit.id().clone()).collect();
+    assert_eq!(ids, [third.id().clone(), first.id().clone()]);
+
+    let fixes = propose_fixes(&ctx, &linted).await.unwrap();
+    assert!(fixes.iter().all(|fix| fix.remaining.is_empty()));
+    let prompt = &mock.requests()[1][1].content;
+    assert!(prompt.starts_with(
+        "Current message:\nadded a.\n\nProblems to fix:\n- subject ends with a period\n"
+    ));
+
+    apply_fixes(ctx, &fixes).unwrap();
+    assert_eq!(test_repo.current(&first).description(), "Add a file\n");
+    assert_eq!(test_repo.current(&third).description(), "Add a file\n");
+    let second = test_repo.current(&second);
+    assert_eq!(second.description(), "Add b");
+    assert_eq!(second.parent_ids(), &[test_repo.current(&first).id().clone()]);
+}
+
+#[tokio::test]
+async fn fixes_keep_the_original_trailers() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(
+        &[],
+        &[("a.txt", "a\n")],
+        "added a.\n\nChange-Id: I1234\nSigned-off-by: Test User <test@example.com>\n",
+    );
+
+    let mut ctx = test_repo.context("ai.standard = \"generic\"");
+    let mock = Arc::new(MockProvider::new(Some("Add a file")));
+    ctx.provider = mock.clone();
+
+    let linted = run_lint(&ctx, &commit.id().hex()).await.unwrap();
+    let fixes = propose_fixes(&ctx, &linted).await.unwrap();
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("Current message:\nadded a.\n\nProblems to fix:\n"));
+    assert!(!prompt.contains("Change-Id"));
+
+    apply_fixes(ctx, &fixes).unwrap();
+    assert_eq!(
+        test_repo.current(&commit).description(),
+        "Add a file\n\nChange-Id: I1234\nSigned-off-by: Test User <test@example.com>\n"
+    );
+}
+
+#[tokio::test]
+async fn checks_custom_standards_from_config() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "a\n")], "[AUTH] Add login\n\nRefs: AUTH-1\n");
+    let second = test_repo.commit(&[&first], &[("b.txt", "b\n")], "Add logout");
+
+    let ctx = test_repo.context(
+        r#"
+        ai.standard = "team"
+        [ai.standards.team]
+        instructions = "Start the subject with the component in brackets."
+        examples = ["[AUTH] Add login\n\nRefs: AUTH-1"]
+        max-subject-length = 40
+        patterns.component = '^\[[A-Z]+\] '
+        patterns.refs = '(?m)^Refs: [A-Z]+-\d+$'
+        "#,
+    );
+    let instructions = ctx.cfg.standard().prompt_instructions();
+    assert!(instructions.starts_with("Start the subject with the component in brackets.\n\n"));
+    assert!(instructions.contains("\n---\n[AUTH] Add login\n\nRefs: AUTH-1\n---"));
+
+    let revision = format!("{}::{}", first.id().hex(), second.id().hex());
+    let linted = run_lint(&ctx, &revision).await.unwrap();
+    assert_eq!(linted.len(), 1);
+    assert_eq!(linted[0].commit.id(), second.id());
+    let rules: Vec<&str> = linted[0].violations.iter().map(|v| v.rule.as_str()).collect();
+    assert_eq!(rules, ["component", "refs"]);
+
+    let invalid = CommandContext::load(
+        &test_repo.root,
+        common::base_config(
+            "ai.standard = \"team\"\n\
+             ai.standards.team.instructions = \"x\"\n\
+             ai.standards.team.patterns.bad = '('",
+        ),
+    );
+    let err = invalid.err().unwrap();
+    assert_eq!(err.to_string(), "invalid pattern for ai.standards.team.patterns.bad");
+}
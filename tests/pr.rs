@@ -0,0 +1,65 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::run_pr;
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+const PULL_REQUEST: &str = r###"{"title": "Add login", "body": "## Summary\n\nAdds login."}"###;
+
+#[tokio::test]
+async fn lists_commits_oldest_first_above_trunk() {
+    let mut test_repo = TestRepo::init();
+    let trunk = test_repo.commit(&[], &[("README", "hi\n")], "Initial commit");
+    let first = test_repo.commit(&[&trunk], &[("README", "hi\n"), ("auth.rs", "a\n")], "Add auth");
+    let second = test_repo.commit(
+        &[&first],
+        &[("README", "hi\n"), ("auth.rs", "a\n"), ("login.rs", "l\n")],
+        "",
+    );
+
+    let mut ctx = test_repo.context(&format!(
+        "revset-aliases.'trunk()' = '{}'",
+        trunk.id().hex()
+    ));
+    let mock = Arc::new(MockProvider::new(Some(PULL_REQUEST)));
+    ctx.provider = mock.clone();
+
+    let pr = run_pr(ctx, &format!("trunk()..{}", second.id().hex()))
+        .await
+        .unwrap();
+    assert_eq!(pr.title, "Add login");
+    assert_eq!(pr.body, "## Summary\n\nAdds login.");
+
+    let requests = mock.requests();
+    assert!(requests[0][0].content.contains("## Testing"));
+    let prompt = &requests[0][1].content;
+    let first_id = &first.change_id().reverse_hex()[..8];
+    let second_id = &second.change_id().reverse_hex()[..8];
+    assert!(prompt.starts_with(&format!("Commit {first_id}\nAdd auth\ndiff --git")));
+    assert!(prompt.contains(&format!("Commit {second_id}\n(no description)\ndiff --git a/login.rs")));
+    assert!(!prompt.contains("Initial commit"));
+}
+
+#[tokio::test]
+async fn fills_in_repository_template() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("auth.rs", "a\n")], "Add auth");
+    std::fs::create_dir(test_repo.root.join(".github")).unwrap();
+    std::fs::write(
+        test_repo.root.join(".github/pull_request_template.md"),
+        "## What\n\n## Checklist\n- [ ] Tests\n",
+    )
+    .unwrap();
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(PULL_REQUEST)));
+    ctx.provider = mock.clone();
+
+    run_pr(ctx, &commit.id().hex()).await.unwrap();
+    let system = &mock.requests()[0][0].content;
+    assert!(system.ends_with("## What\n\n## Checklist\n- [ ] Tests\n"));
+    assert!(!system.contains("## Testing"));
+}
@@ -0,0 +1,477 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures::future::join_all;
+use jj_ai::llm::{
+    structured_output, AnthropicProvider, ApiError, ChatMessage, MockProvider, OpenAiProvider,
+    OpenRouterProvider, Provider, ThrottledProvider,
+};
+use orpheus::prelude::*;
+use reqwest::StatusCode;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Serves a single HTTP/1.1 request with `reply` as the JSON body and returns
+/// the raw request that was received.
+async fn serve_once(reply: serde_json::Value) -> (String, tokio::task::JoinHandle<String>) {
+    serve_body("application/json", reply.to_string()).await
+}
+
+async fn serve_body(
+    content_type: &'static str,
+    body: String,
+) -> (String, tokio::task::JoinHandle<String>) {
+    serve("200 OK", content_type, body).await
+}
+
+/// Like [`serve_body`], replying with `status` instead of `200 OK`.
+async fn serve(
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+) -> (String, tokio::task::JoinHandle<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = socket.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            let text = String::from_utf8_lossy(&request);
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length = head
+                    .lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length: ").map(str::to_string))
+                    .and_then(|l| l.trim().parse::<usize>().ok())
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    break;
+                }
+            }
+        }
+
+        let response = format!(
+            "HTTP/1.1 {status}\r\ncontent-type: {content_type}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        socket.write_all(response.as_bytes()).await.unwrap();
+        String::from_utf8(request).unwrap()
+    });
+
+    (base_url, handle)
+}
+
+fn message_format() -> Format {
+    Format::json("message")
+        .with_schema(|schema| {
+            schema
+                .property("message", Param::string())
+                .required(["message"])
+        })
+        .build()
+}
+
+#[tokio::test]
+async fn openai_provider_speaks_chat_completions_without_key() {
+    let (base_url, server) = serve_once(serde_json::json!({
+        "choices": [{ "message": { "role": "assistant", "content": "{\"message\":\"Fix bug\"}" } }]
+    }))
+    .await;
+
+    let provider = OpenAiProvider::new(&base_url, None);
+    let output: serde_json::Value = structured_output(
+        &provider,
+        "local-model",
+        &[ChatMessage::system("sys"), ChatMessage::user("diff")],
+        &message_format(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(output["message"], "Fix bug");
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /v1/chat/completions HTTP/1.1"));
+    assert!(!request.to_lowercase().contains("authorization:"));
+
+    let body: serde_json::Value =
+        serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap();
+    assert_eq!(body["model"], "local-model");
+    assert_eq!(body["messages"][1]["role"], "user");
+    assert_eq!(body["response_format"]["type"], "json_schema");
+}
+
+#[tokio::test]
+async fn openai_provider_streams_server_sent_events() {
+    let events = [
+        r#"{"choices":[{"delta":{"role":"assistant"}}]}"#,
+        r#"{"choices":[{"delta":{"content":"Hello"}}]}"#,
+        r#"{"choices":[{"delta":{"content":", world"}}]}"#,
+        "[DONE]",
+    ];
+    let body: String = events.iter().map(|e| format!("data: {e}\n\n")).collect();
+    let (base_url, server) = serve_body("text/event-stream", body).await;
+
+    let provider = OpenAiProvider::new(&base_url, None);
+    let mut pieces = Vec::new();
+    provider
+        .chat_stream("m", &[ChatMessage::user("hi")], &mut |text| {
+            pieces.push(text.to_string())
+        })
+        .await
+        .unwrap();
+    assert_eq!(pieces, ["Hello", ", world"]);
+
+    let request = server.await.unwrap();
+    assert!(request.contains(r#""stream":true"#));
+}
+
+#[tokio::test]
+async fn openai_provider_rejects_responses_cut_off_at_the_length_limit() {
+    let (base_url, _server) = serve_once(serde_json::json!({
+        "choices": [{
+            "message": { "role": "assistant", "content": "fn main() {" },
+            "finish_reason": "length"
+        }]
+    }))
+    .await;
+
+    let provider = OpenAiProvider::new(&base_url, None);
+    let err = provider.chat("m", &[ChatMessage::user("hi")]).await.unwrap_err();
+    assert_eq!(err.to_string(), "chat completion was cut off at the model's output limit");
+}
+
+/// The JSON body of a raw HTTP request.
+fn request_json(request: &str) -> serde_json::Value {
+    serde_json::from_str(request.split_once("\r\n\r\n").unwrap().1).unwrap()
+}
+
+#[tokio::test]
+async fn anthropic_provider_forces_a_tool_for_structured_output() {
+    let (base_url, server) = serve_once(serde_json::json!({
+        "content": [
+            { "type": "text", "text": "Here you go" },
+            { "type": "tool_use", "id": "t1", "name": "message", "input": { "message": "Fix bug" } }
+        ]
+    }))
+    .await;
+
+    let provider = AnthropicProvider::new(&base_url, "secret");
+    let output: serde_json::Value = structured_output(
+        &provider,
+        "claude",
+        &[
+            ChatMessage::system("sys one"),
+            ChatMessage::system("sys two"),
+            ChatMessage::user("diff"),
+        ],
+        &message_format(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(output["message"], "Fix bug");
+
+    let request = server.await.unwrap();
+    assert!(request.starts_with("POST /v1/messages HTTP/1.1"));
+    let headers = request.to_lowercase();
+    assert!(headers.contains("x-api-key: secret"));
+    assert!(headers.contains("anthropic-version: "));
+
+    let body = request_json(&request);
+    assert_eq!(body["model"], "claude");
+    assert_eq!(body["system"], "sys one\n\nsys two");
+    assert_eq!(body["messages"], serde_json::json!([{ "role": "user", "content": "diff" }]));
+    assert_eq!(body["tools"][0]["name"], "message");
+    assert_eq!(body["tools"][0]["input_schema"]["required"][0], "message");
+    assert_eq!(body["tool_choice"], serde_json::json!({ "type": "tool", "name": "message" }));
+}
+
+#[tokio::test]
+async fn anthropic_provider_joins_text_blocks_and_streams() {
+    let (base_url, server) = serve_once(serde_json::json!({
+        "content": [{ "type": "text", "text": "Hello" }, { "type": "text", "text": ", world" }]
+    }))
+    .await;
+    let provider = AnthropicProvider::new(&base_url, "secret");
+    let reply = provider.chat("claude", &[ChatMessage::user("hi")]).await.unwrap();
+    assert_eq!(reply, "Hello, world");
+    assert!(request_json(&server.await.unwrap()).get("system").is_none());
+
+    let events = [
+        r#"{"type":"message_start","message":{}}"#,
+        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hel"}}"#,
+        r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"lo"}}"#,
+        r#"{"type":"message_stop"}"#,
+    ];
+    let body: String = events.iter().map(|e| format!("event: x\ndata: {e}\n\n")).collect();
+    let (base_url, server) = serve_body("text/event-stream", body).await;
+    let provider = AnthropicProvider::new(&base_url, "secret");
+    let mut pieces = Vec::new();
+    provider
+        .chat_stream("claude", &[ChatMessage::user("hi")], &mut |text| {
+            pieces.push(text.to_string())
+        })
+        .await
+        .unwrap();
+    assert_eq!(pieces, ["Hel", "lo"]);
+    assert_eq!(request_json(&server.await.unwrap())["stream"], true);
+}
+
+#[tokio::test]
+async fn anthropic_provider_rejects_responses_cut_off_at_max_tokens() {
+    let (base_url, server) = serve_once(serde_json::json!({
+        "content": [{ "type": "text", "text": "fn main() {" }],
+        "stop_reason": "max_tokens"
+    }))
+    .await;
+
+    let provider = AnthropicProvider::new(&base_url, "secret").with_max_tokens(16000);
+    let err = provider.chat("claude", &[ChatMessage::user("hi")]).await.unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Anthropic response was cut off at the output limit, raise ai.max-output-tokens"
+    );
+    assert_eq!(request_json(&server.await.unwrap())["max_tokens"], 16000);
+}
+
+#[tokio::test]
+async fn anthropic_provider_maps_errors_to_api_errors() {
+    let body = r#"{"type":"error","error":{"type":"rate_limit_error"}}"#;
+    let (base_url, _server) = serve("429 Too Many Requests", "application/json", body.to_string()).await;
+
+    let provider = AnthropicProvider::new(&base_url, "secret");
+    let err = provider.chat("claude", &[ChatMessage::user("hi")]).await.unwrap_err();
+    let api_error = err.downcast_ref::<ApiError>().unwrap();
+    assert_eq!(api_error.service, "Anthropic API");
+    assert_eq!(api_error.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(api_error.body, body);
+}
+
+/// Serves a single HTTP/2 request, as OpenRouter's client only speaks
+/// HTTP/2, and returns the request line and JSON body that were received.
+async fn serve_h2_once(
+    reply: serde_json::Value,
+) -> (String, tokio::task::JoinHandle<(String, serde_json::Value)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+
+    let handle = tokio::spawn(async move {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut connection = h2::server::handshake(socket).await.unwrap();
+        let (request, mut respond) = connection.accept().await.unwrap().unwrap();
+        let (parts, mut body) = request.into_parts();
+        let line = format!(
+            "{} {} authorization: {}",
+            parts.method,
+            parts.uri.path(),
+            parts.headers["authorization"].to_str().unwrap()
+        );
+        let mut received = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk.unwrap();
+            body.flow_control().release_capacity(chunk.len()).unwrap();
+            received.extend_from_slice(&chunk);
+        }
+
+        let response = http::Response::builder()
+            .header("content-type", "application/json")
+            .body(())
+            .unwrap();
+        let mut send = respond.send_response(response, false).unwrap();
+        send.send_data(bytes::Bytes::from(reply.to_string()), true)
+            .unwrap();
+        // Drive the connection until the client has read the reply.
+        tokio::spawn(async move { while connection.accept().await.is_some() {} });
+        (line, serde_json::from_slice(&received).unwrap())
+    });
+
+    (base_url, handle)
+}
+
+fn openrouter_completion(content: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": "gen-1",
+        "provider": "test",
+        "model": "openai/gpt-4o-mini",
+        "object": "chat.completion",
+        "created": 0,
+        "choices": [{
+            "index": 0,
+            "finish_reason": "stop",
+            "message": { "role": "assistant", "content": content }
+        }],
+        "usage": { "prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2 }
+    })
+}
+
+#[tokio::test]
+async fn openrouter_provider_uses_base_url_and_response_format() {
+    let (base_url, server) = serve_h2_once(openrouter_completion("{\"message\":\"Fix bug\"}")).await;
+
+    let provider = OpenRouterProvider::new(Some(&base_url), "secret").unwrap();
+    let output: serde_json::Value = structured_output(
+        &provider,
+        "openai/gpt-4o-mini",
+        &[ChatMessage::system("sys"), ChatMessage::user("diff")],
+        &message_format(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(output["message"], "Fix bug");
+
+    let (line, body) = server.await.unwrap();
+    assert_eq!(line, "POST /v1/chat/completions authorization: Bearer secret");
+    assert_eq!(body["model"], "openai/gpt-4o-mini");
+    assert_eq!(body["messages"][0]["role"], "system");
+    assert_eq!(body["messages"][1]["content"], "diff");
+    assert_eq!(body["response_format"]["type"], "json_schema");
+}
+
+#[tokio::test]
+async fn openrouter_provider_chats_and_rejects_invalid_base_urls() {
+    let (base_url, server) = serve_h2_once(openrouter_completion("Hello")).await;
+    let provider = OpenRouterProvider::new(Some(&base_url), "secret").unwrap();
+    let reply = provider.chat("m", &[ChatMessage::user("hi")]).await.unwrap();
+    assert_eq!(reply, "Hello");
+    let (_, body) = server.await.unwrap();
+    assert_eq!(body["model"], "m");
+    assert!(body.get("response_format").is_none());
+
+    let err = OpenRouterProvider::new(Some("not a url"), "secret").err().unwrap();
+    assert_eq!(err.to_string(), "invalid ai.base-url 'not a url'");
+}
+
+#[tokio::test]
+async fn mock_provider_wraps_plain_responses() {
+    let provider = MockProvider::new(Some("Plain message"));
+    let value = provider
+        .structured("m", &[ChatMessage::user("diff")], &message_format())
+        .await
+        .unwrap();
+    assert_eq!(value, serde_json::json!({ "message": "Plain message" }));
+
+    let text = provider.chat("m", &[ChatMessage::user("hi")]).await.unwrap();
+    assert_eq!(text, "Plain message");
+    assert_eq!(provider.requests().len(), 2);
+}
+
+/// Fails with `status` for the first `failures` calls and tracks how many
+/// calls are in flight at once.
+struct FlakyProvider {
+    status: StatusCode,
+    failures: usize,
+    calls: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl FlakyProvider {
+    fn new(status: StatusCode, failures: usize) -> Self {
+        Self {
+            status,
+            failures,
+            calls: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl FlakyProvider {
+    async fn call(&self) -> anyhow::Result<String> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        if call < self.failures {
+            return Err(ApiError {
+                service: "test",
+                status: self.status,
+                body: String::new(),
+            }
+            .into());
+        }
+        Ok(format!("reply {call}"))
+    }
+}
+
+#[async_trait]
+impl Provider for FlakyProvider {
+    async fn chat(&self, _model: &str, _messages: &[ChatMessage]) -> anyhow::Result<String> {
+        self.call().await
+    }
+
+    async fn structured(
+        &self,
+        _model: &str,
+        _messages: &[ChatMessage],
+        _format: &Format,
+    ) -> anyhow::Result<serde_json::Value> {
+        Ok(serde_json::json!({ "message": self.call().await? }))
+    }
+}
+
+#[tokio::test]
+async fn throttled_provider_retries_rate_limits() {
+    let flaky = Arc::new(FlakyProvider::new(StatusCode::TOO_MANY_REQUESTS, 2));
+    let provider = ThrottledProvider::new(flaky.clone(), 1, 3);
+
+    let reply = provider.chat("m", &[ChatMessage::user("hi")]).await.unwrap();
+    assert_eq!(reply, "reply 2");
+    assert_eq!(flaky.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn throttled_provider_retries_structured_requests() {
+    let flaky = Arc::new(FlakyProvider::new(StatusCode::TOO_MANY_REQUESTS, 1));
+    let provider = ThrottledProvider::new(flaky.clone(), 1, 3);
+
+    let value = provider
+        .structured("m", &[ChatMessage::user("diff")], &message_format())
+        .await
+        .unwrap();
+    assert_eq!(value, serde_json::json!({ "message": "reply 1" }));
+    assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn throttled_provider_does_not_retry_client_errors() {
+    let flaky = Arc::new(FlakyProvider::new(StatusCode::BAD_REQUEST, 1));
+    let provider = ThrottledProvider::new(flaky.clone(), 1, 3);
+
+    let err = provider.chat("m", &[ChatMessage::user("hi")]).await.unwrap_err();
+    assert_eq!(err.to_string(), "test error 400 Bad Request: ");
+    assert_eq!(flaky.calls.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn throttled_provider_limits_concurrency() {
+    let flaky = Arc::new(FlakyProvider::new(StatusCode::OK, 0));
+    let provider = ThrottledProvider::new(flaky.clone(), 2, 0);
+
+    let messages = [ChatMessage::user("hi")];
+    let replies = join_all((0..6).map(|_| provider.chat("m", &messages))).await;
+    assert!(replies.iter().all(Result::is_ok));
+    assert_eq!(flaky.max_in_flight.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn throttled_provider_retries_streams_before_first_text() {
+    let flaky = Arc::new(FlakyProvider::new(StatusCode::SERVICE_UNAVAILABLE, 1));
+    let provider = ThrottledProvider::new(flaky.clone(), 1, 3);
+
+    let mut streamed = String::new();
+    provider
+        .chat_stream("m", &[ChatMessage::user("hi")], &mut |text| streamed.push_str(text))
+        .await
+        .unwrap();
+    assert_eq!(streamed, "reply 1");
+    assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+}
@@ -0,0 +1,93 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{apply_resolve, plan_resolve};
+use jj_ai::llm::MockProvider;
+use jj_lib::merge::Merge;
+use jj_lib::merged_tree::MergedTree;
+use jj_lib::object_id::ObjectId;
+use jj_lib::repo::Repo;
+use jj_lib::repo_path::RepoPathBuf;
+use pollster::FutureExt;
+
+#[tokio::test]
+async fn resolves_conflicts_and_rebases_descendants() {
+    let mut test_repo = TestRepo::init();
+    let base = test_repo.commit(&[], &[("file.txt", "base\n"), ("ok.txt", "ok\n")], "base");
+    let side1 = test_repo.commit(&[&base], &[("file.txt", "one\n"), ("ok.txt", "ok\n")], "side 1");
+    let side2_tree = test_repo.tree(&[("file.txt", "two\n"), ("ok.txt", "ok\n")]);
+    let conflicted_tree = MergedTree::merge_no_resolve(Merge::from_vec(vec![
+        (side1.tree(), "side 1".to_string()),
+        (base.tree(), "base".to_string()),
+        (side2_tree, "side 2".to_string()),
+    ]));
+    let commit = test_repo.commit_tree(&[&side1], conflicted_tree, "Merge sides");
+    let child = test_repo.commit(&[&commit], &[("file.txt", "child\n")], "child");
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some("one\ntwo")));
+    ctx.provider = mock.clone();
+
+    let plan = plan_resolve(&ctx, &commit.id().hex()).await.unwrap();
+    assert!(plan.skipped.is_empty());
+    assert_eq!(plan.files.len(), 1);
+    // The missing final newline is restored.
+    assert_eq!(plan.files[0].content, "one\ntwo\n");
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("File: file.txt\n\nBase 1 (base):\n```\nbase\n```\n"));
+    assert!(prompt.contains("Side 1 (side 1):\n```\none\n```\n"));
+    assert!(prompt.contains("Side 2 (side 2):\n```\ntwo\n```\n"));
+
+    let patch = plan.files[0].patch.to_string();
+    assert!(patch.contains("\n-<<<<<<< "));
+    assert!(patch.contains("\n+one\n"));
+    assert!(patch.contains("\n->>>>>>> "));
+
+    apply_resolve(ctx, plan).await.unwrap();
+    let resolved = test_repo.current(&commit);
+    assert!(!resolved.has_conflict());
+    assert_eq!(resolved.description(), "Merge sides");
+
+    let path = RepoPathBuf::from_internal_string("file.txt").unwrap();
+    let value = resolved.tree().path_value(&path).unwrap();
+    let jj_lib::backend::TreeValue::File { id, .. } = value.as_normal().unwrap() else {
+        panic!("expected a file");
+    };
+    let mut reader = test_repo.repo.store().read_file(&path, id).block_on().unwrap();
+    let mut content = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut content)
+        .await
+        .unwrap();
+    assert_eq!(content, b"one\ntwo\n");
+
+    let child = test_repo.current(&child);
+    assert_eq!(child.parent_ids(), &[resolved.id().clone()]);
+}
+
+#[tokio::test]
+async fn skips_resolutions_with_markers() {
+    let mut test_repo = TestRepo::init();
+    let base = test_repo.commit(&[], &[("file.txt", "base\n")], "base");
+    let side1 = test_repo.commit(&[&base], &[("file.txt", "one\n")], "side 1");
+    let conflicted_tree = MergedTree::merge_no_resolve(Merge::from_vec(vec![
+        (side1.tree(), "side 1".to_string()),
+        (base.tree(), "base".to_string()),
+        (test_repo.tree(&[("file.txt", "two\n")]), "side 2".to_string()),
+    ]));
+    let commit = test_repo.commit_tree(&[&side1], conflicted_tree, "");
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some("<<<<<<< ours\none\n>>>>>>> theirs\n")));
+    let plan = plan_resolve(&ctx, &commit.id().hex()).await.unwrap();
+    assert!(plan.files.is_empty());
+    assert_eq!(
+        plan.skipped,
+        vec![("file.txt".to_string(), "resolution still has conflict markers")]
+    );
+
+    let err = plan_resolve(&ctx, &base.id().hex()).await.err().unwrap();
+    assert_eq!(err.to_string(), "commit has no conflicts");
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{render_annotated, run_review, to_json, to_sarif};
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+const FINDINGS: &str = r#"{"findings": [
+    {"path": "src/lib.rs", "start_line": 2, "end_line": 2, "severity": "error", "message": "Division by zero"},
+    {"path": "README.md", "start_line": 1, "end_line": 3, "severity": "info", "message": "Document this"}
+]}"#;
+
+#[tokio::test]
+async fn reviews_with_numbered_diff_and_annotations() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(
+        &[],
+        &[("src/lib.rs", "fn f(x: u32) -> u32 {\n    100 / x\n}\n")],
+        "Add f",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(FINDINGS)));
+    ctx.provider = mock.clone();
+
+    let reviews = run_review(ctx, &commit.id().hex()).await.unwrap();
+    assert_eq!(reviews.len(), 1);
+    assert_eq!(reviews[0].findings.len(), 2);
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.contains("\n    2 +    100 / x\n"));
+
+    let change_id = commit.change_id().reverse_hex();
+    assert_eq!(
+        render_annotated(&reviews),
+        format!(
+            "=== {} Add f ===\n\
+             diff --git a/src/lib.rs b/src/lib.rs\n\
+             new file mode 100644\n\
+             @@ -1,0 +1,3 @@\n\
+             +fn f(x: u32) -> u32 {{\n\
+             +    100 / x\n\
+             \x20   ^ [error] Division by zero\n\
+             +}}\n\
+             README.md:1-3: [info] Document this\n\n",
+            &change_id[..8]
+        )
+    );
+
+    let json = to_json(&reviews);
+    assert_eq!(json[0]["change_id"], change_id);
+    assert_eq!(json[0]["findings"][0]["severity"], "info");
+
+    let sarif = to_sarif(&reviews);
+    assert_eq!(sarif["version"], "2.1.0");
+    let result = &sarif["runs"][0]["results"][1];
+    assert_eq!(result["level"], "error");
+    assert_eq!(result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "src/lib.rs");
+    assert_eq!(result["locations"][0]["physicalLocation"]["region"]["startLine"], 2);
+}
+
+#[tokio::test]
+async fn tolerates_bad_line_numbers_from_the_model() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("src/lib.rs", "fn f() {}\n")], "Add f");
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(
+        r#"{"findings": [
+            {"path": "a.rs", "start_line": 5, "end_line": 2, "severity": "info", "message": "Reversed"},
+            {"path": "b.rs", "start_line": -1, "end_line": 4, "severity": "info", "message": "Negative"},
+            {"path": "c.rs", "start_line": null, "end_line": null, "severity": "info", "message": "Missing"}
+        ]}"#,
+    )));
+
+    let reviews = run_review(ctx, &commit.id().hex()).await.unwrap();
+    let sarif = to_sarif(&reviews);
+    let regions: Vec<_> = sarif["runs"][0]["results"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|result| {
+            let region = &result["locations"][0]["physicalLocation"]["region"];
+            (region["startLine"].as_u64().unwrap(), region["endLine"].as_u64().unwrap())
+        })
+        .collect();
+    assert_eq!(regions, [(2, 5), (4, 4), (1, 1)]);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{apply_split, plan_split};
+use jj_ai::llm::MockProvider;
+use jj_lib::commit::Commit;
+use jj_lib::object_id::ObjectId;
+use jj_lib::repo::Repo;
+use jj_lib::repo_path::RepoPathBuf;
+use pollster::FutureExt;
+
+fn read(test_repo: &TestRepo, commit: &Commit, path: &str) -> Option<String> {
+    let path = RepoPathBuf::from_internal_string(path).unwrap();
+    let value = commit.tree().path_value(&path).unwrap();
+    let jj_lib::backend::TreeValue::File { id, .. } = value.as_normal()? else {
+        return None;
+    };
+    let mut reader = test_repo.repo.store().read_file(&path, id).block_on().unwrap();
+    let mut content = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut content)
+        .block_on()
+        .unwrap();
+    Some(String::from_utf8(content).unwrap())
+}
+
+#[tokio::test]
+async fn splits_files_into_a_chain_of_commits() {
+    let mut test_repo = TestRepo::init();
+    let base = test_repo.commit(&[], &[("a.txt", "a\n"), ("b.txt", "b\n")], "base");
+    let commit = test_repo.commit(
+        &[&base],
+        &[("a.txt", "a2\n"), ("b.txt", "b2\n"), ("c.txt", "c\n")],
+        "",
+    );
+    let child = test_repo.commit(
+        &[&commit],
+        &[("a.txt", "a2\n"), ("b.txt", "b2\n"), ("c.txt", "c2\n")],
+        "child",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(
+        r#"{"commits": [
+            {"description": "Refactor a and c", "changes": ["[a.txt]", "c.txt"]},
+            {"description": "Fix b", "changes": ["b.txt"]}
+        ]}"#,
+    )));
+    ctx.provider = mock.clone();
+
+    let plan = plan_split(&ctx, &commit.id().hex()).await.unwrap();
+    assert_eq!(plan.commits.len(), 2);
+    let paths: Vec<_> = plan.commits[0].changes.iter().map(|c| c.path.as_str()).collect();
+    assert_eq!(paths, ["a.txt", "c.txt"]);
+    assert!(plan.commits[1].changes[0].hunks.is_none());
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with("[a.txt]\ndiff --git a/a.txt b/a.txt\n"));
+
+    let commits = apply_split(ctx, plan).await.unwrap();
+    assert_eq!(commits.len(), 2);
+    test_repo.reload();
+
+    let first = test_repo.current(&commit);
+    assert_eq!(first.id(), commits[0].id());
+    assert_eq!(first.description(), "Refactor a and c");
+    assert_eq!(read(&test_repo, &first, "a.txt").as_deref(), Some("a2\n"));
+    assert_eq!(read(&test_repo, &first, "b.txt").as_deref(), Some("b\n"));
+    assert_eq!(read(&test_repo, &first, "c.txt").as_deref(), Some("c\n"));
+
+    let second = &commits[1];
+    assert_eq!(second.parent_ids(), &[first.id().clone()]);
+    assert_eq!(second.tree_ids(), commit.tree_ids());
+
+    let child = test_repo.current(&child);
+    assert_eq!(child.parent_ids(), &[second.id().clone()]);
+}
+
+#[tokio::test]
+async fn splits_hunks_of_one_file() {
+    let mut test_repo = TestRepo::init();
+    let lines: Vec<String> = (1..=20).map(|i| format!("line {i}\n")).collect();
+    let before = lines.concat();
+    let mut changed = lines.clone();
+    changed[0] = "first\n".to_string();
+    changed[19] = "last\n".to_string();
+    let after = changed.concat();
+
+    let base = test_repo.commit(&[], &[("f.txt", &before)], "base");
+    let commit = test_repo.commit(&[&base], &[("f.txt", &after)], "");
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(
+        r#"{"commits": [
+            {"description": "Change the end", "changes": ["f.txt#2"]},
+            {"description": "Change the start", "changes": []}
+        ]}"#,
+    )));
+    ctx.provider = mock.clone();
+
+    let plan = plan_split(&ctx, &commit.id().hex()).await.unwrap();
+    assert_eq!(plan.commits[0].changes[0].hunks, Some(vec![1]));
+    // The unassigned first hunk goes into the last commit.
+    assert_eq!(plan.commits[1].changes[0].hunks, Some(vec![0]));
+    assert!(mock.requests()[0][1].content.contains("[f.txt#1]\n@@ -1,4 +1,4 @@\n"));
+
+    let commits = apply_split(ctx, plan).await.unwrap();
+    let mut expected = lines.clone();
+    expected[19] = "last\n".to_string();
+    assert_eq!(read(&test_repo, &commits[0], "f.txt"), Some(expected.concat()));
+    assert_eq!(read(&test_repo, &commits[1], "f.txt"), Some(after));
+}
+
+#[tokio::test]
+async fn rejects_unknown_changes() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(&[], &[("a.txt", "a\n")], "");
+
+    let mut ctx = test_repo.context("");
+    ctx.provider = Arc::new(MockProvider::new(Some(
+        r#"{"commits": [{"description": "Add z", "changes": ["z.txt"]}]}"#,
+    )));
+
+    let err = plan_split(&ctx, &commit.id().hex()).await.err().unwrap();
+    assert_eq!(err.to_string(), "the model referenced an unknown change 'z.txt'");
+}
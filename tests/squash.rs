@@ -0,0 +1,111 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{apply_squashes, plan_squashes};
+use jj_ai::llm::MockProvider;
+use jj_lib::object_id::ObjectId;
+
+#[tokio::test]
+async fn squashes_model_confirmed_fixups() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "helo\n")], "Add a");
+    let second = test_repo.commit(&[&first], &[("a.txt", "helo\n"), ("b.txt", "b\n")], "Add b");
+    let fixup = test_repo.commit(&[&second], &[("a.txt", "hello\n"), ("b.txt", "b\n")], "fix typo");
+    let child = test_repo.commit(
+        &[&fixup],
+        &[("a.txt", "hello\n"), ("b.txt", "b\n"), ("c.txt", "c\n")],
+        "Add c",
+    );
+
+    let first_id = first.change_id().reverse_hex()[..8].to_string();
+    let fixup_id = fixup.change_id().reverse_hex()[..8].to_string();
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(&format!(
+        r#"{{"squashes": [
+            {{"source": "{fixup_id}", "target": "{first_id}", "reason": "Fixes a typo in a"}},
+            {{"source": "{fixup_id}", "target": "zzzzzzzz", "reason": "Unknown target"}}
+        ]}}"#
+    ))));
+    ctx.provider = mock.clone();
+
+    let revision = format!("{}::{}", first.id().hex(), child.id().hex());
+    let plan = plan_squashes(&ctx, &revision).await.unwrap();
+    assert_eq!(plan.squashes.len(), 1);
+    assert_eq!(plan.squashes[0].source.id(), fixup.id());
+    assert_eq!(plan.squashes[0].target.id(), first.id());
+    assert_eq!(plan.squashes[0].reason, "Fixes a typo in a");
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.starts_with(&format!(
+        "Candidate squashes:\n- {fixup_id} into {first_id}: touches a.txt, all of which the \
+         target touched; 1 hunk(s) change lines the target changed\n\nCommit {first_id}\nAdd a\n"
+    )));
+    assert!(!prompt.contains("Add b"));
+
+    apply_squashes(ctx, plan).await.unwrap();
+    let first = test_repo.current(&first);
+    assert_eq!(first.description(), "Add a");
+    assert_eq!(first.tree_ids(), test_repo.tree(&[("a.txt", "hello\n")]).tree_ids());
+    let second = test_repo.current(&second);
+    assert_eq!(second.parent_ids(), &[first.id().clone()]);
+    let child = test_repo.current(&child);
+    assert_eq!(child.parent_ids(), &[second.id().clone()]);
+    let expected = test_repo.tree(&[("a.txt", "hello\n"), ("b.txt", "b\n"), ("c.txt", "c\n")]);
+    assert_eq!(child.tree_ids(), expected.tree_ids());
+}
+
+#[tokio::test]
+async fn follows_fixup_subjects_without_the_model() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "a\n")], "Add a");
+    let second = test_repo.commit(&[&first], &[("a.txt", "a\n"), ("b.txt", "b\n")], "Add b");
+    let fixup = test_repo.commit(
+        &[&second],
+        &[("a.txt", "a\n"), ("b.txt", "b\n"), ("a2.txt", "a\n")],
+        "fixup! Add a",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(None));
+    ctx.provider = mock.clone();
+
+    let revision = format!("{}::{}", first.id().hex(), fixup.id().hex());
+    let plan = plan_squashes(&ctx, &revision).await.unwrap();
+    assert_eq!(plan.squashes.len(), 1);
+    assert_eq!(plan.squashes[0].target.id(), first.id());
+    assert_eq!(plan.squashes[0].reason, "marked as a fixup of \"Add a\"");
+    assert!(mock.requests().is_empty());
+}
+
+#[tokio::test]
+async fn context_lines_do_not_count_as_overlap() {
+    let mut test_repo = TestRepo::init();
+    let lines: Vec<String> = (1..=10).map(|i| format!("line {i}\n")).collect();
+    let file = |changes: &[(usize, &str)]| {
+        let mut lines = lines.clone();
+        for (index, line) in changes {
+            lines[*index] = line.to_string();
+        }
+        lines.concat()
+    };
+    let base = test_repo.commit(&[], &[("a.txt", &file(&[]))], "Add a");
+    let target = test_repo.commit(&[&base], &[("a.txt", &file(&[(1, "two\n")]))], "Change two");
+    let source = test_repo.commit(
+        &[&target],
+        &[("a.txt", &file(&[(1, "two\n"), (4, "five\n")]))],
+        "Change five",
+    );
+
+    let mut ctx = test_repo.context("");
+    let mock = Arc::new(MockProvider::new(Some(r#"{"squashes": []}"#)));
+    ctx.provider = mock.clone();
+
+    let revision = format!("{}::{}", target.id().hex(), source.id().hex());
+    let plan = plan_squashes(&ctx, &revision).await.unwrap();
+    assert!(plan.squashes.is_empty());
+
+    let prompt = &mock.requests()[0][1].content;
+    assert!(prompt.contains(": touches a.txt, all of which the target touched\n"));
+}
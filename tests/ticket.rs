@@ -0,0 +1,100 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::TestRepo;
+use jj_ai::command::{run_describe, DescribeMode};
+use jj_ai::config::{CommitStandard, TicketPlacement};
+use jj_ai::lint::lint_description;
+use jj_ai::llm::MockProvider;
+use jj_ai::ticket::{extract_tickets, insert_tickets, missing_tickets};
+use jj_lib::object_id::ObjectId;
+use regex::Regex;
+
+#[test]
+fn extracts_tickets_from_bookmarks() {
+    let pattern = Regex::new(r"[A-Z][A-Z0-9]+-\d+").unwrap();
+    let names = ["feat/PROJ-12-login", "PROJ-12", "fix/OPS-3", "main"].map(String::from);
+    assert_eq!(extract_tickets(&pattern, &names), ["PROJ-12", "OPS-3"]);
+}
+
+#[test]
+fn inserts_missing_tickets_per_placement() {
+    let tickets = ["PROJ-12".to_string()];
+    let generic = CommitStandard::Generic;
+    assert_eq!(
+        insert_tickets("feat(auth)!: add login\n\nBody.\n", &tickets, TicketPlacement::Scope, &generic),
+        "feat(auth, PROJ-12)!: add login\n\nBody.\n"
+    );
+    assert_eq!(
+        insert_tickets("Add login", &tickets, TicketPlacement::Scope, &generic),
+        "Add login\n\nRefs: PROJ-12\n"
+    );
+    assert_eq!(
+        insert_tickets("Add login\n", &tickets, TicketPlacement::Prefix, &generic),
+        "PROJ-12: Add login\n"
+    );
+    assert_eq!(
+        insert_tickets(
+            "Add login\n\nSigned-off-by: A <a@example.com>\n",
+            &tickets,
+            TicketPlacement::Trailer,
+            &generic
+        ),
+        "Add login\n\nSigned-off-by: A <a@example.com>\nRefs: PROJ-12\n"
+    );
+    assert_eq!(
+        insert_tickets("feat(PROJ-12): add login\n", &tickets, TicketPlacement::Trailer, &generic),
+        "feat(PROJ-12): add login\n"
+    );
+}
+
+#[test]
+fn prefix_tickets_follow_the_type_or_gitmoji() {
+    let tickets = ["PROJ-12".to_string()];
+    for (standard, description, expected) in [
+        (CommitStandard::Conventional, "feat(auth)!: add login\n", "feat(auth)!: PROJ-12 add login\n"),
+        (CommitStandard::Gitmoji, "✨ add login\n", "✨ PROJ-12 add login\n"),
+        (CommitStandard::Gitmoji, ":bug: fix login\n", ":bug: PROJ-12 fix login\n"),
+    ] {
+        let description = insert_tickets(description, &tickets, TicketPlacement::Prefix, &standard);
+        assert_eq!(description, expected);
+        assert!(lint_description(&standard, &description).is_empty());
+    }
+}
+
+#[test]
+fn tickets_are_matched_as_whole_words() {
+    let tickets = ["PROJ-1".to_string(), "PROJ-12".to_string()];
+    let generic = CommitStandard::Generic;
+    assert_eq!(missing_tickets("feat(PROJ-12): add login\n", &tickets), ["PROJ-1"]);
+    assert_eq!(missing_tickets("Fix PROJ-1, PROJ-12\n", &tickets), Vec::<&str>::new());
+    assert_eq!(
+        insert_tickets("Add login\n\nRefs: PROJ-12\n", &tickets[..1], TicketPlacement::Trailer, &generic),
+        "Add login\n\nRefs: PROJ-12\nRefs: PROJ-1\n"
+    );
+}
+
+#[tokio::test]
+async fn describe_references_tickets_of_descendant_bookmarks() {
+    let mut test_repo = TestRepo::init();
+    let first = test_repo.commit(&[], &[("a.txt", "a\n")], "");
+    let second = test_repo.commit(&[&first], &[("b.txt", "b\n")], "Add b");
+    test_repo.set_bookmark("feat/PROJ-12-login", &second);
+
+    let mut ctx = test_repo.context(
+        "ai.standard = \"conventional\"\n\
+         ai.ticket-pattern = '[A-Z]+-\\d+'",
+    );
+    let mock = Arc::new(MockProvider::new(Some("feat: add a")));
+    ctx.provider = mock.clone();
+    let result = run_describe(ctx, &first.id().hex(), false, DescribeMode::Fill, false)
+        .await
+        .unwrap();
+
+    assert_eq!(result.described[0].description, "feat(PROJ-12): add a\n");
+    assert!(mock.requests()[0][1]
+        .content
+        .contains("Reference PROJ-12 as the scope of the header, e.g. `feat(PROJ-12): ...`.\n"));
+    assert_eq!(test_repo.current(&first).description(), "feat(PROJ-12): add a\n");
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::TestRepo;
+use jj_ai::command::{run_describe, DescribeMode};
+use jj_ai::trailer::{merge_trailers, parse_trailer, split_trailers};
+use jj_lib::object_id::ObjectId;
+
+#[test]
+fn splits_the_trailer_paragraph() {
+    let (text, trailers) = split_trailers("Add login\n\nWhy.\n\nRefs: PROJ-1\nAcked-by: B\n");
+    assert_eq!(text, "Add login\n\nWhy.");
+    assert_eq!(
+        trailers,
+        [parse_trailer("Refs: PROJ-1").unwrap(), parse_trailer("Acked-by: B").unwrap()]
+    );
+
+    assert_eq!(split_trailers("Refs: PROJ-1\n"), ("Refs: PROJ-1", vec![]));
+    assert!(parse_trailer("not a trailer").is_err());
+}
+
+#[test]
+fn merges_trailers_into_one_block() {
+    let added = [
+        parse_trailer("Signed-off-by: A <a@example.com>").unwrap(),
+        parse_trailer("AI-Model: new-model").unwrap(),
+    ];
+    assert_eq!(
+        merge_trailers(
+            "Old message\n\nCo-authored-by: B <b@example.com>\nAI-Model: old-model\n",
+            "New message\n\nBody.\n\nsigned-off-by: A <a@example.com>\n",
+            &added,
+        ),
+        "New message\n\nBody.\n\n\
+         Co-authored-by: B <b@example.com>\n\
+         signed-off-by: A <a@example.com>\n\
+         AI-Model: new-model\n"
+    );
+    assert_eq!(merge_trailers("", "New message", &[]), "New message");
+}
+
+#[tokio::test]
+async fn describe_keeps_and_adds_trailers() {
+    let mut test_repo = TestRepo::init();
+    let commit = test_repo.commit(
+        &[],
+        &[("a.txt", "a\n")],
+        "Old message\n\nCo-authored-by: B <b@example.com>\n",
+    );
+
+    let ctx = test_repo.context(
+        "ai.trailers.signed-off-by = true\n\
+         ai.trailers.model = true\n\
+         ai.trailers.add = [\"Reviewed-by: C <c@example.com>\"]",
+    );
+    run_describe(ctx, &commit.id().hex(), false, DescribeMode::Overwrite, false)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        test_repo.current(&commit).description(),
+        "Mock description\n\n\
+         Co-authored-by: B <b@example.com>\n\
+         Reviewed-by: C <c@example.com>\n\
+         Signed-off-by: Test User <test.user@example.com>\n\
+         AI-Model: mock-model\n"
+    );
+}